@@ -0,0 +1,137 @@
+//! Compares the lock-free `Translator` with the previous design, where every key
+//! owned an `Arc<Mutex<TemplatePool>>` of `tera::Tera` instances held for the whole render.
+//!
+//! Run with `cargo bench --bench translate`. The contention benchmark needs several
+//! cores to show the cost of the per-key mutex.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use megacommerce_proto::{TranslationElement, TranslationElements};
+use megacommerce_shared::models::translate::Translator;
+
+const HOT_KEY: &str = "server.internal.error";
+const HOT_TEMPLATE: &str = "Sorry {{ name }}, unexpected error in {{ path }}. Request: {{ id }}";
+const THREADS: usize = 8;
+const RENDERS_PER_THREAD: u64 = 200;
+
+/// The previous per-key pool, kept here for comparison only
+struct TemplatePool {
+  available: VecDeque<tera::Tera>,
+  template_str: String,
+  max_size: usize,
+}
+
+impl TemplatePool {
+  fn get(&mut self) -> tera::Tera {
+    self.available.pop_front().unwrap_or_else(|| {
+      let mut t = tera::Tera::default();
+      t.add_raw_template("pooled_template", &self.template_str).unwrap();
+      t
+    })
+  }
+
+  fn return_instance(&mut self, instance: tera::Tera) {
+    if self.available.len() < self.max_size {
+      self.available.push_back(instance);
+    }
+  }
+}
+
+type PoolStore = HashMap<String, Arc<Mutex<TemplatePool>>>;
+
+fn pool_tr(store: &PoolStore, id: &str, params: &HashMap<&str, &str>) -> String {
+  let mut guard = store[id].lock().unwrap();
+  let tera = guard.get();
+  let context = tera::Context::from_serialize(params).unwrap();
+  let result = tera.render("pooled_template", &context).unwrap();
+  guard.return_instance(tera);
+  result
+}
+
+fn catalog(keys: usize) -> Vec<(String, String)> {
+  let mut items: Vec<_> =
+    (0..keys).map(|i| (format!("key.{i}"), format!("Message {i} for {{{{ name }}}}"))).collect();
+  items.push((HOT_KEY.to_string(), HOT_TEMPLATE.to_string()));
+  items
+}
+
+fn translator(keys: usize) -> Translator {
+  let trans =
+    catalog(keys).into_iter().map(|(id, tr)| TranslationElement { id, tr }).collect::<Vec<_>>();
+  let trans = HashMap::from([("en".to_string(), TranslationElements { trans })]);
+  Translator::new(trans, "en".to_string(), vec!["en".to_string()])
+}
+
+fn pool_store(keys: usize, max_size: usize) -> PoolStore {
+  catalog(keys)
+    .into_iter()
+    .map(|(id, tr)| {
+      let pool = TemplatePool { available: VecDeque::new(), template_str: tr, max_size };
+      (id, Arc::new(Mutex::new(pool)))
+    })
+    .collect()
+}
+
+fn params() -> HashMap<&'static str, &'static str> {
+  HashMap::from([("name", "Sam"), ("path", "orders.create"), ("id", "01J9Z3")])
+}
+
+/// Total time of `THREADS` threads rendering `iters` times each
+fn contended<F: Fn() + Send + Sync + 'static>(iters: u64, render: F) -> Duration {
+  let render = Arc::new(render);
+  let start = Instant::now();
+  let handles: Vec<_> = (0..THREADS)
+    .map(|_| {
+      let render = render.clone();
+      thread::spawn(move || (0..iters).for_each(|_| render()))
+    })
+    .collect();
+  handles.into_iter().for_each(|h| h.join().unwrap());
+  start.elapsed()
+}
+
+fn bench_single_thread(c: &mut Criterion) {
+  let mut group = c.benchmark_group("tr_single_thread");
+  let t = translator(1000);
+  let pools = pool_store(1000, 8);
+  let p = params();
+
+  group.bench_function("lock_free", |b| b.iter(|| t.tr("en", HOT_KEY, Some(&p)).unwrap()));
+  group.bench_function("mutex_pool", |b| b.iter(|| pool_tr(&pools, HOT_KEY, &p)));
+  group.finish();
+}
+
+fn bench_hot_key_contention(c: &mut Criterion) {
+  let mut group = c.benchmark_group("tr_hot_key_contention");
+  group.throughput(criterion::Throughput::Elements(THREADS as u64 * RENDERS_PER_THREAD));
+
+  let t = Arc::new(translator(1000));
+  group.bench_with_input(BenchmarkId::new("lock_free", THREADS), &t, |b, t| {
+    b.iter_custom(|iters| {
+      let t = t.clone();
+      let p = params();
+      contended(iters * RENDERS_PER_THREAD, move || {
+        t.tr("en", HOT_KEY, Some(&p)).unwrap();
+      })
+    })
+  });
+
+  let pools = Arc::new(pool_store(1000, 8));
+  group.bench_with_input(BenchmarkId::new("mutex_pool", THREADS), &pools, |b, pools| {
+    b.iter_custom(|iters| {
+      let pools = pools.clone();
+      let p = params();
+      contended(iters * RENDERS_PER_THREAD, move || {
+        pool_tr(&pools, HOT_KEY, &p);
+      })
+    })
+  });
+  group.finish();
+}
+
+criterion_group!(benches, bench_single_thread, bench_hot_key_contention);
+criterion_main!(benches);
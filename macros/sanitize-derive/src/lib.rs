@@ -1,6 +1,10 @@
 use proc_macro::TokenStream;
-use quote::quote;
-use syn::{parse_macro_input, punctuated::Punctuated, Path, Token};
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{quote, quote_spanned};
+use syn::{
+  Data, DeriveInput, Fields, Index, Path, Token, parse_macro_input, punctuated::Punctuated,
+  spanned::Spanned,
+};
 
 #[proc_macro]
 pub fn sanitize_app_error(input: TokenStream) -> TokenStream {
@@ -16,11 +20,13 @@ pub fn sanitize_app_error(input: TokenStream) -> TokenStream {
   let ty = &args[0]; // e.g. EmailConfirmationResponse
   let enum_ty = &args[1]; // e.g. email_confirmation_response::Response
 
+  let errors = quote!(::megacommerce_shared::models::errors);
   let expanded = quote! {
-      impl SanitizeAppError for #ty {
+      impl #errors::SanitizeAppError for #ty {
           fn sanitize_app_error_fields(&mut self) {
-              if let Some(#enum_ty::Error(err)) = self.response.take() {
-                  self.response = Some(#enum_ty::Error(sanitize_app_error(&err)));
+              // as_mut, not take: a data response must be left in place
+              if let Some(#enum_ty::Error(err)) = self.response.as_mut() {
+                  *err = #errors::sanitize_app_error(err);
               }
           }
       }
@@ -28,3 +34,141 @@ pub fn sanitize_app_error(input: TokenStream) -> TokenStream {
 
   expanded.into()
 }
+
+/// Derives `SanitizeAppError` for a response, sanitizing the fields marked with `#[app_error]`:
+///
+/// ```ignore
+/// #[derive(SanitizeAppError)]
+/// struct OrderResponse {
+///   // prost oneofs: the variants holding an error or a nested response
+///   #[app_error(oneof(order_response::Response::Error))]
+///   response: Option<order_response::Response>,
+///   // an error, or a nested response implementing `SanitizeAppError`,
+///   // directly or in an `Option`, a `Vec` or a `Box`
+///   #[app_error]
+///   warning: Option<AppError>,
+///   #[app_error]
+///   items: Vec<OrderItemResponse>,
+/// }
+/// ```
+#[proc_macro_derive(SanitizeAppError, attributes(app_error))]
+pub fn derive_sanitize_app_error(input: TokenStream) -> TokenStream {
+  let input = parse_macro_input!(input as DeriveInput);
+  derive(&input).unwrap_or_else(|e| e.to_compile_error()).into()
+}
+
+enum Target {
+  Field,
+  Oneof(Vec<Path>),
+}
+
+fn derive(input: &DeriveInput) -> syn::Result<TokenStream2> {
+  let errors = quote!(::megacommerce_shared::models::errors);
+  let fields = match &input.data {
+    Data::Struct(data) => &data.fields,
+    Data::Enum(data) => {
+      return Err(syn::Error::new(
+        data.enum_token.span,
+        "`SanitizeAppError` can only be derived for structs, mark the struct field holding \
+         the enum with `#[app_error(oneof(...))]` instead",
+      ));
+    }
+    Data::Union(data) => {
+      return Err(syn::Error::new(
+        data.union_token.span,
+        "`SanitizeAppError` can only be derived for structs",
+      ));
+    }
+  };
+
+  let mut stmts = vec![];
+  for (i, field) in fields.iter().enumerate() {
+    let Some(target) = target(field)? else { continue };
+    let member = match &field.ident {
+      Some(ident) => quote!(#ident),
+      None => {
+        let index = Index::from(i);
+        quote!(#index)
+      }
+    };
+
+    // spanned on the field, so a type not implementing the trait is reported there
+    let stmt = match target {
+      Target::Field => quote_spanned! {field.ty.span()=>
+        #errors::SanitizeAppError::sanitize_app_error_fields(&mut self.#member);
+      },
+      Target::Oneof(variants) => {
+        let arms = variants.iter().map(|v| {
+          quote_spanned! {v.span()=>
+            if let ::core::option::Option::Some(#v(inner)) =
+              ::core::option::Option::as_mut(&mut self.#member)
+            {
+              #errors::SanitizeAppError::sanitize_app_error_fields(inner);
+            }
+          }
+        });
+        quote!(#(#arms)*)
+      }
+    };
+    stmts.push(stmt);
+  }
+
+  if stmts.is_empty() {
+    let msg = match fields {
+      Fields::Unit => "`SanitizeAppError` needs a field marked with `#[app_error]`",
+      _ => "no field is marked with `#[app_error]` or `#[app_error(oneof(...))]`",
+    };
+    return Err(syn::Error::new(input.ident.span(), msg));
+  }
+
+  let ident = &input.ident;
+  let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+  Ok(quote! {
+    impl #impl_generics #errors::SanitizeAppError for #ident #ty_generics #where_clause {
+      fn sanitize_app_error_fields(&mut self) {
+        #(#stmts)*
+      }
+    }
+  })
+}
+
+fn target(field: &syn::Field) -> syn::Result<Option<Target>> {
+  let mut result = None;
+  for attr in field.attrs.iter().filter(|a| a.path().is_ident("app_error")) {
+    if result.is_some() {
+      return Err(syn::Error::new_spanned(attr, "duplicate `#[app_error]` attribute"));
+    }
+    if let syn::Meta::Path(_) = attr.meta {
+      result = Some(Target::Field);
+      continue;
+    }
+
+    let mut variants = None;
+    attr.parse_nested_meta(|meta| {
+      if !meta.path.is_ident("oneof") {
+        return Err(meta.error("unknown app_error attribute, expected `oneof(Enum::Variant, ..)`"));
+      }
+      if variants.is_some() {
+        return Err(meta.error("duplicate `oneof`"));
+      }
+      let content;
+      syn::parenthesized!(content in meta.input);
+      let paths = Punctuated::<Path, Token![,]>::parse_terminated(&content)?;
+      if paths.is_empty() {
+        return Err(meta.error("expected the variants holding an error: `oneof(Enum::Error)`"));
+      }
+      variants = Some(paths.into_iter().collect());
+      Ok(())
+    })?;
+    match variants {
+      Some(variants) => result = Some(Target::Oneof(variants)),
+      None => {
+        return Err(syn::Error::new_spanned(
+          attr,
+          "expected `#[app_error]` or `#[app_error(oneof(Enum::Variant, ..))]`",
+        ));
+      }
+    }
+  }
+  Ok(result)
+}
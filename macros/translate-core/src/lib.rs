@@ -0,0 +1,152 @@
+//! The parts of the translations handling shared by `megacommerce-shared` and the
+//! `translation_keys!` macro, so that both read the files, parse the messages and find
+//! the variables of a translation the same way.
+
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+use serde_json::Value;
+use tera::ast::{Expr, ExprVal, FunctionCall, Node};
+
+mod message;
+mod plural;
+
+pub use message::{Message, Part, PluralSelector};
+pub use plural::PluralCategory;
+
+/// Flattens a map of translations into `out`, nested maps are joined with dots
+pub fn flatten(
+  value: &Value,
+  prefix: String,
+  out: &mut BTreeMap<String, String>,
+) -> Result<(), String> {
+  match value {
+    Value::Object(map) => {
+      for (k, v) in map {
+        let key = if prefix.is_empty() { k.clone() } else { format!("{prefix}.{k}") };
+        flatten(v, key, out)?;
+      }
+    }
+    _ if prefix.is_empty() => return Err("expected a map of translations".to_string()),
+    Value::String(s) => {
+      out.insert(prefix, s.clone());
+    }
+    Value::Number(n) => {
+      out.insert(prefix, n.to_string());
+    }
+    Value::Bool(b) => {
+      out.insert(prefix, b.to_string());
+    }
+    Value::Null | Value::Array(_) => return Err(format!("{prefix}: expected a string")),
+  }
+  Ok(())
+}
+
+/// A Tera error with its causes, which hold the actual parse error
+pub fn tera_error_string(e: &tera::Error) -> String {
+  let mut msg = e.to_string();
+  let mut source = std::error::Error::source(e);
+  while let Some(s) = source {
+    msg.push_str(&format!(": {s}"));
+    source = s.source();
+  }
+  msg
+}
+
+/// Collects the root names of the variables a Tera template reads from its context
+#[derive(Debug, Default)]
+pub struct Vars {
+  used: BTreeSet<String>,
+  /// `for`/`set` variables, which don't come from the params
+  locals: HashSet<String>,
+}
+
+impl Vars {
+  /// The variables read, without the locals
+  pub fn into_variables(self) -> BTreeSet<String> {
+    self.used.into_iter().filter(|v| !self.locals.contains(v)).collect()
+  }
+
+  pub fn ident(&mut self, ident: &str) {
+    let root = ident.split(['.', '[']).next().unwrap_or(ident).trim();
+    if !root.is_empty() && root != "loop" && root != "__tera_context" {
+      self.used.insert(root.to_string());
+    }
+  }
+
+  pub fn nodes(&mut self, nodes: &[Node]) {
+    nodes.iter().for_each(|n| self.node(n));
+  }
+
+  fn node(&mut self, node: &Node) {
+    match node {
+      Node::VariableBlock(_, expr) => self.expr(expr),
+      Node::Set(_, set) => {
+        self.locals.insert(set.key.clone());
+        self.expr(&set.value);
+      }
+      Node::FilterSection(_, section, _) => {
+        self.call(&section.filter);
+        self.nodes(&section.body);
+      }
+      Node::Block(_, block, _) => self.nodes(&block.body),
+      Node::Forloop(_, for_loop, _) => {
+        self.locals.insert(for_loop.value.clone());
+        if let Some(key) = &for_loop.key {
+          self.locals.insert(key.clone());
+        }
+        self.expr(&for_loop.container);
+        self.nodes(&for_loop.body);
+        if let Some(body) = &for_loop.empty_body {
+          self.nodes(body);
+        }
+      }
+      Node::If(cond, _) => {
+        for (_, expr, body) in &cond.conditions {
+          self.expr(expr);
+          self.nodes(body);
+        }
+        if let Some((_, body)) = &cond.otherwise {
+          self.nodes(body);
+        }
+      }
+      Node::MacroDefinition(_, def, _) => self.nodes(&def.body),
+      _ => {}
+    }
+  }
+
+  fn call(&mut self, call: &FunctionCall) {
+    call.args.values().for_each(|e| self.expr(e));
+  }
+
+  fn expr(&mut self, expr: &Expr) {
+    self.expr_val(&expr.val);
+    expr.filters.iter().for_each(|f| self.call(f));
+  }
+
+  fn expr_val(&mut self, val: &ExprVal) {
+    match val {
+      ExprVal::Ident(ident) => self.ident(ident),
+      ExprVal::Math(m) => {
+        self.expr(&m.lhs);
+        self.expr(&m.rhs);
+      }
+      ExprVal::Logic(l) => {
+        self.expr(&l.lhs);
+        self.expr(&l.rhs);
+      }
+      ExprVal::In(i) => {
+        self.expr(&i.lhs);
+        self.expr(&i.rhs);
+      }
+      ExprVal::Test(t) => {
+        self.ident(&t.ident);
+        t.args.iter().for_each(|e| self.expr(e));
+      }
+      ExprVal::MacroCall(m) => m.args.values().for_each(|e| self.expr(e)),
+      ExprVal::FunctionCall(f) => self.call(f),
+      ExprVal::Array(items) => items.iter().for_each(|e| self.expr(e)),
+      ExprVal::StringConcat(c) => c.values.iter().for_each(|v| self.expr_val(v)),
+      ExprVal::String(_) | ExprVal::Int(_) | ExprVal::Float(_) | ExprVal::Bool(_) => {}
+    }
+  }
+}
@@ -0,0 +1,257 @@
+use std::collections::BTreeSet;
+use std::sync::LazyLock;
+
+use regex::Regex;
+
+use crate::plural::PluralCategory;
+use crate::{Vars, tera_error_string};
+
+/// Matches the header of an ICU block, e.g. `{count, plural,` or `{gender, select,`
+static BLOCK_HEADER: LazyLock<Regex> = LazyLock::new(|| {
+  Regex::new(r"^\{\s*([A-Za-z_][A-Za-z0-9_.]*)\s*,\s*(plural|select)\s*,").unwrap()
+});
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PluralSelector {
+  Exact(f64),
+  Category(PluralCategory),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Part {
+  /// Index of a Tera template in `Message::leaves`
+  Text(usize),
+  Plural {
+    var: String,
+    branches: Vec<(PluralSelector, Vec<Part>)>,
+  },
+  Select {
+    var: String,
+    branches: Vec<(String, Vec<Part>)>,
+  },
+}
+
+/// A translation split into ICU `plural`/`select` blocks and the Tera templates
+/// between them. A translation without ICU blocks is a single leaf.
+///
+/// ```text
+/// {count, plural, =0 {No items} one {# item left} other {# items left}}
+/// {gender, select, female {She} male {He} other {They}} liked {{ product }}
+/// ```
+///
+/// `#` inside a plural branch is replaced with the plural variable.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+  pub parts: Vec<Part>,
+  pub leaves: Vec<String>,
+}
+
+impl Message {
+  /// Parses `src`, a syntax error in an ICU block is returned as a message
+  pub fn parse(src: &str) -> Result<Self, String> {
+    let mut parser = Parser { src, pos: 0, leaves: vec![] };
+    let parts = parser.parse_parts(None, false)?;
+    Ok(Self { parts, leaves: parser.leaves })
+  }
+
+  /// Treats `src` as a single Tera template
+  pub fn plain(src: &str) -> Self {
+    Self { parts: vec![Part::Text(0)], leaves: vec![src.to_string()] }
+  }
+
+  pub fn has_blocks(&self) -> bool {
+    self.parts.iter().any(|p| !matches!(p, Part::Text(_)))
+  }
+
+  /// The root names of the variables of the message, from its plural/select blocks and
+  /// its Tera templates
+  pub fn variables(&self) -> Result<BTreeSet<String>, String> {
+    let mut vars = Vars::default();
+    part_vars(&self.parts, &mut vars);
+    for leaf in &self.leaves {
+      let template =
+        tera::Template::new("message", None, leaf).map_err(|e| tera_error_string(&e))?;
+      vars.nodes(&template.ast);
+    }
+    Ok(vars.into_variables())
+  }
+}
+
+fn part_vars(parts: &[Part], vars: &mut Vars) {
+  for part in parts {
+    match part {
+      Part::Text(_) => {}
+      Part::Plural { var, branches } => {
+        vars.ident(var);
+        branches.iter().for_each(|(_, parts)| part_vars(parts, vars));
+      }
+      Part::Select { var, branches } => {
+        vars.ident(var);
+        branches.iter().for_each(|(_, parts)| part_vars(parts, vars));
+      }
+    }
+  }
+}
+
+struct Parser<'a> {
+  src: &'a str,
+  pos: usize,
+  leaves: Vec<String>,
+}
+
+impl Parser<'_> {
+  fn flush(&mut self, text: &mut String, parts: &mut Vec<Part>) {
+    if !text.is_empty() {
+      self.leaves.push(std::mem::take(text));
+      parts.push(Part::Text(self.leaves.len() - 1));
+    }
+  }
+
+  /// Parses until the end of input, or until the `}` closing a branch when `nested`
+  fn parse_parts(&mut self, plural_var: Option<&str>, nested: bool) -> Result<Vec<Part>, String> {
+    let mut parts = vec![];
+    let mut text = String::new();
+
+    loop {
+      let rest = &self.src[self.pos..];
+      let Some(ch) = rest.chars().next() else {
+        if nested {
+          return Err("unclosed plural/select branch".to_string());
+        }
+        break;
+      };
+
+      // Tera tags are copied verbatim
+      let tera_close = ["{{", "{%", "{#"]
+        .iter()
+        .zip(["}}", "%}", "#}"])
+        .find(|(open, _)| rest.starts_with(**open))
+        .map(|(_, close)| close);
+      if let Some(close) = tera_close {
+        let end = rest[2..].find(close).map_or(rest.len(), |i| i + 4);
+        text.push_str(&rest[..end]);
+        self.pos += end;
+        continue;
+      }
+
+      if ch == '{'
+        && let Some(block) = self.parse_block(plural_var)?
+      {
+        self.flush(&mut text, &mut parts);
+        parts.push(block);
+        continue;
+      }
+
+      self.pos += ch.len_utf8();
+      match (ch, plural_var) {
+        ('}', _) if nested => {
+          self.flush(&mut text, &mut parts);
+          return Ok(parts);
+        }
+        ('#', Some(var)) => text.push_str(&format!("{{{{ {var} }}}}")),
+        _ => text.push(ch),
+      }
+    }
+
+    self.flush(&mut text, &mut parts);
+    Ok(parts)
+  }
+
+  fn skip_whitespace(&mut self) {
+    let rest = &self.src[self.pos..];
+    self.pos += rest.len() - rest.trim_start().len();
+  }
+
+  fn parse_block(&mut self, plural_var: Option<&str>) -> Result<Option<Part>, String> {
+    let Some(caps) = BLOCK_HEADER.captures(&self.src[self.pos..]) else {
+      return Ok(None);
+    };
+    let var = caps[1].to_string();
+    let is_plural = &caps[2] == "plural";
+    self.pos += caps[0].len();
+
+    let mut plural_branches = vec![];
+    let mut select_branches = vec![];
+    loop {
+      self.skip_whitespace();
+      let rest = &self.src[self.pos..];
+      if rest.starts_with('}') {
+        self.pos += 1;
+        break;
+      }
+
+      let len =
+        rest.find(|c: char| c.is_whitespace() || c == '{' || c == '}').unwrap_or(rest.len());
+      let key = rest[..len].to_string();
+      if key.is_empty() {
+        return Err(format!("unclosed {} block for `{var}`", &caps[2]));
+      }
+      self.pos += len;
+      self.skip_whitespace();
+      if !self.src[self.pos..].starts_with('{') {
+        return Err(format!("expected `{{` after `{key}` in the block for `{var}`"));
+      }
+      self.pos += 1;
+
+      if is_plural {
+        let selector = match key.strip_prefix('=') {
+          Some(n) => PluralSelector::Exact(
+            n.parse().map_err(|_| format!("invalid exact plural selector `{key}`"))?,
+          ),
+          None => PluralSelector::Category(
+            PluralCategory::parse(&key)
+              .ok_or_else(|| format!("unknown plural category `{key}`"))?,
+          ),
+        };
+        plural_branches.push((selector, self.parse_parts(Some(&var), true)?));
+      } else {
+        select_branches.push((key, self.parse_parts(plural_var, true)?));
+      }
+    }
+
+    let has_other =
+      plural_branches.iter().any(|(s, _)| *s == PluralSelector::Category(PluralCategory::Other))
+        || select_branches.iter().any(|(k, _)| k == "other");
+    if !has_other {
+      return Err(format!("the block for `{var}` is missing the `other` branch"));
+    }
+
+    Ok(Some(if is_plural {
+      Part::Plural { var, branches: plural_branches }
+    } else {
+      Part::Select { var, branches: select_branches }
+    }))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn vars(src: &str) -> Vec<String> {
+    Message::parse(src).unwrap().variables().unwrap().into_iter().collect()
+  }
+
+  #[test]
+  fn test_plain_message() {
+    let msg = Message::parse("Hello {{ name }}, {not a block}").unwrap();
+    assert!(!msg.has_blocks());
+    assert_eq!(msg.leaves, vec!["Hello {{ name }}, {not a block}"]);
+  }
+
+  #[test]
+  fn test_invalid_blocks() {
+    assert!(Message::parse("{n, plural, one {x}}").is_err());
+    assert!(Message::parse("{n, plural, lots {x} other {y}}").is_err());
+    assert!(Message::parse("{n, select, a {x} other {y}").is_err());
+  }
+
+  #[test]
+  fn test_variables() {
+    let src = "{gender, select, female {She has {n, plural, one {# {{ unit }}} other {# items}}} \
+               other {{% for o in orders %}{{ o.id }}{% endfor %}}}";
+    assert_eq!(vars(src), vec!["gender", "n", "orders", "unit"]);
+    assert_eq!(vars("{{ user.name | upper }} {not a block}"), vec!["user"]);
+    assert!(Message::parse("{{ a ").unwrap().variables().is_err());
+  }
+}
@@ -0,0 +1,43 @@
+use std::fmt;
+
+/// CLDR plural categories
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PluralCategory {
+  Zero,
+  One,
+  Two,
+  Few,
+  Many,
+  Other,
+}
+
+impl PluralCategory {
+  pub const fn as_str(&self) -> &'static str {
+    match self {
+      Self::Zero => "zero",
+      Self::One => "one",
+      Self::Two => "two",
+      Self::Few => "few",
+      Self::Many => "many",
+      Self::Other => "other",
+    }
+  }
+
+  pub fn parse(s: &str) -> Option<Self> {
+    match s {
+      "zero" => Some(Self::Zero),
+      "one" => Some(Self::One),
+      "two" => Some(Self::Two),
+      "few" => Some(Self::Few),
+      "many" => Some(Self::Many),
+      "other" => Some(Self::Other),
+      _ => None,
+    }
+  }
+}
+
+impl fmt::Display for PluralCategory {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", self.as_str())
+  }
+}
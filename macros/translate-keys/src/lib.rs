@@ -0,0 +1,149 @@
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::path::PathBuf;
+
+use megacommerce_shared_translate_core::{Message, flatten};
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{format_ident, quote};
+use serde_json::Value;
+use syn::{Ident, LitStr, parse_macro_input};
+
+/// Generates a typed key for every translation of a JSON or YAML file (a map of ids to
+/// translations, nested maps are flattened with dots). Like `include_str!`, the path is
+/// relative to the file the macro is called in:
+///
+/// ```ignore
+/// pub mod keys {
+///   megacommerce_shared::translation_keys!("../translations/en.json");
+/// }
+///
+/// // "order.not_found": "Order {{ id }} is not found"
+/// translator.tr_typed("en", keys::ORDER_NOT_FOUND, keys::OrderNotFoundParams { id: 5.into() })
+/// ```
+///
+/// For each id this generates a unit struct (`OrderNotFound`) implementing
+/// `megacommerce_shared::models::translate::TypedKey`, a constant of it
+/// (`ORDER_NOT_FOUND`) and, if the translation has variables, a params struct
+/// (`OrderNotFoundParams`) with one field per variable. Keys without variables take `()`.
+#[proc_macro]
+pub fn translation_keys(input: TokenStream) -> TokenStream {
+  let path = parse_macro_input!(input as LitStr);
+  expand(&path).unwrap_or_else(|e| e.to_compile_error()).into()
+}
+
+fn expand(lit: &LitStr) -> syn::Result<TokenStream2> {
+  let err = |msg: String| syn::Error::new(lit.span(), msg);
+
+  // a relative source path is relative to the working directory of rustc
+  let file = proc_macro::Span::call_site().local_file();
+  let dir = match file.as_deref().and_then(|f| f.parent()) {
+    Some(dir) => std::env::current_dir().map_err(|e| err(e.to_string()))?.join(dir),
+    None => {
+      std::env::var("CARGO_MANIFEST_DIR").map(PathBuf::from).map_err(|e| err(e.to_string()))?
+    }
+  };
+  let path = dir.join(lit.value());
+  let content =
+    std::fs::read_to_string(&path).map_err(|e| err(format!("{}: {e}", path.display())))?;
+  let value: Value = match path.extension().and_then(|e| e.to_str()) {
+    Some("json") => serde_json::from_str(&content).map_err(|e| err(e.to_string()))?,
+    Some("yaml" | "yml") => serde_yaml::from_str(&content).map_err(|e| err(e.to_string()))?,
+    _ => return Err(err("expected a .json, .yaml or .yml file".to_string())),
+  };
+
+  let mut trans = BTreeMap::new();
+  flatten(&value, String::new(), &mut trans).map_err(err)?;
+
+  let mut idents = HashSet::new();
+  let mut items = vec![];
+  for (id, source) in &trans {
+    let name = pascal_case(id);
+    if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+      return Err(err(format!("`{id}` can't be turned into an identifier")));
+    }
+    if !idents.insert(name.clone()) {
+      return Err(err(format!("`{id}` has the same identifier as another key: {name}")));
+    }
+    // parsed like the runtime catalog does, so both find the same variables
+    let vars = Message::parse(source)
+      .and_then(|msg| msg.variables())
+      .map_err(|e| err(format!("`{id}`: {e}")))?;
+    items.push(key_tokens(id, source, &name, &vars));
+  }
+
+  let path = path.display().to_string();
+  Ok(quote! {
+    // recompile when the file changes
+    const _: &str = include_str!(#path);
+    #(#items)*
+  })
+}
+
+fn key_tokens(id: &str, source: &str, name: &str, vars: &BTreeSet<String>) -> TokenStream2 {
+  let krate = quote!(::megacommerce_shared::models::translate);
+  let key = Ident::new(name, Span::call_site());
+  let constant = Ident::new(&screaming_snake_case(id), Span::call_site());
+  let doc = format!("`{id}`: {source}");
+
+  let (params_ty, params_item) = if vars.is_empty() {
+    (quote!(()), quote!())
+  } else {
+    let params = format_ident!("{}Params", name);
+    let fields: Vec<_> = vars.iter().map(|v| field_ident(v)).collect();
+    let names = vars.iter();
+    let params_doc = format!("The params of `{id}`");
+    let item = quote! {
+      #[doc = #params_doc]
+      #[derive(Debug, Clone, PartialEq)]
+      pub struct #params {
+        #(pub #fields: #krate::ParamValue,)*
+      }
+
+      impl #krate::TypedParams for #params {
+        fn into_params(self) -> ::std::option::Option<::std::collections::HashMap<::std::string::String, #krate::ParamValue>> {
+          ::std::option::Option::Some(::std::collections::HashMap::from([
+            #((::std::string::String::from(#names), self.#fields),)*
+          ]))
+        }
+      }
+    };
+    (quote!(#params), item)
+  };
+
+  quote! {
+    #[doc = #doc]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub struct #key;
+
+    impl #krate::TypedKey for #key {
+      const ID: &'static str = #id;
+      type Params = #params_ty;
+    }
+
+    #[doc = #doc]
+    pub const #constant: #key = #key;
+
+    #params_item
+  }
+}
+
+fn words(id: &str) -> impl Iterator<Item = &str> {
+  id.split(|c: char| !c.is_ascii_alphanumeric()).filter(|w| !w.is_empty())
+}
+
+fn pascal_case(id: &str) -> String {
+  words(id)
+    .map(|w| {
+      let mut chars = w.chars();
+      chars.next().map(|c| c.to_ascii_uppercase().to_string() + chars.as_str()).unwrap_or_default()
+    })
+    .collect()
+}
+
+fn screaming_snake_case(id: &str) -> String {
+  words(id).map(|w| w.to_ascii_uppercase()).collect::<Vec<_>>().join("_")
+}
+
+fn field_ident(var: &str) -> Ident {
+  syn::parse_str::<Ident>(var).unwrap_or_else(|_| Ident::new_raw(var, Span::call_site()))
+}
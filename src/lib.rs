@@ -9,3 +9,6 @@ pub mod store;
 
 #[cfg(any(feature = "macros", feature = "all"))]
 pub use megacommerce_shared_sanitize_derive::sanitize_app_error;
+
+#[cfg(any(feature = "macros", feature = "all"))]
+pub use megacommerce_shared_translate_keys::translation_keys;
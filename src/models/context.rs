@@ -1,11 +1,12 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, fmt};
 
 use derive_more::Display;
 
 pub type StringMap = HashMap<String, String>;
 
-#[derive(Clone, Debug, Default, Display)]
-#[display("Session: {id} {token} {created_at} {expires_at} {last_activity_at} {user_id} {device_id} {roles} {is_oauth} {props:?}")]
+/// `Display` and `Debug` leave the token out, so a logged session or context can't leak it
+#[derive(Clone, Default, Display)]
+#[display("Session: {id} {created_at} {expires_at} {last_activity_at} {user_id} {device_id} {roles} {is_oauth} {props:?}")]
 pub struct Session {
   pub id: String,
   pub token: String,
@@ -19,6 +20,23 @@ pub struct Session {
   pub props: StringMap,
 }
 
+impl fmt::Debug for Session {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("Session")
+      .field("id", &self.id)
+      .field("token", &"[redacted]")
+      .field("created_at", &self.created_at)
+      .field("expires_at", &self.expires_at)
+      .field("last_activity_at", &self.last_activity_at)
+      .field("user_id", &self.user_id)
+      .field("device_id", &self.device_id)
+      .field("roles", &self.roles)
+      .field("is_oauth", &self.is_oauth)
+      .field("props", &self.props)
+      .finish()
+  }
+}
+
 impl Session {
   pub fn id(&self) -> &str {
     &self.id
@@ -53,7 +71,9 @@ impl Session {
 }
 
 #[derive(Clone, Debug, Default, Display)]
-#[display("Context: {session} {request_id} {ip_address} {x_forwarded_for} {path} {user_agent} {accept_language}")]
+#[display(
+  "Context: {session} {request_id} {ip_address} {x_forwarded_for} {path} {user_agent} {accept_language}"
+)]
 pub struct Context {
   pub session: Session,
   pub request_id: String,
@@ -63,9 +83,12 @@ pub struct Context {
   pub user_agent: String,
   pub accept_language: String,
   pub timezone: String,
+  /// The language negotiated from `accept_language`, empty if not negotiated yet
+  pub language: String,
 }
 
 impl Context {
+  #[allow(clippy::too_many_arguments)]
   pub fn new(
     session: Session,
     request_id: String,
@@ -85,20 +108,14 @@ impl Context {
       user_agent,
       accept_language,
       timezone,
+      language: String::new(),
     }
   }
 
-  pub fn clone(&self) -> Self {
-    Self {
-      session: self.session.clone(),
-      request_id: self.request_id.clone(),
-      ip_address: self.ip_address.clone(),
-      x_forwarded_for: self.x_forwarded_for.clone(),
-      path: self.path.clone(),
-      user_agent: self.user_agent.clone(),
-      accept_language: self.accept_language.clone(),
-      timezone: self.timezone.clone(),
-    }
+  /// Sets the language negotiated from `accept_language` (e.g. with `Translator::negotiate`)
+  pub fn with_language(mut self, language: impl Into<String>) -> Self {
+    self.language = language.into();
+    self
   }
 
   pub fn session(&self) -> Session {
@@ -122,4 +139,8 @@ impl Context {
   pub fn accept_language(&self) -> &str {
     &self.accept_language
   }
+  /// The negotiated language, or the raw `accept_language` if it wasn't negotiated
+  pub fn language(&self) -> &str {
+    if self.language.is_empty() { &self.accept_language } else { &self.language }
+  }
 }
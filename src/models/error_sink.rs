@@ -0,0 +1,471 @@
+use std::{
+  collections::HashMap,
+  error::Error,
+  fs::OpenOptions,
+  io::Write,
+  path::PathBuf,
+  sync::{Arc, LazyLock, Mutex},
+  time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::{runtime::Handle, task::JoinHandle};
+use tokio_util::sync::CancellationToken;
+
+use super::errors::{AppError, BoxedErr, InternalError, error_chain, error_type_of};
+
+/// An error as seen by an `ErrorSink`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ErrorReport {
+  pub id: String,
+  pub path: String,
+  pub err_type: String,
+  /// The messages of the cause chain
+  pub cause: String,
+  pub request_id: String,
+}
+
+impl ErrorReport {
+  /// Identifies the errors that are the same but for the values in their messages (ids,
+  /// numbers, quoted values), e.g. the same failing query for different rows. The `id` is
+  /// normalized too, it's the raw message of an `InternalError` or a `DBError`.
+  pub fn fingerprint(&self) -> String {
+    let mut hasher = Sha256::new();
+    let (id, cause) = (normalize_cause(&self.id), normalize_cause(&self.cause));
+    for part in [&id, &self.path, &self.err_type, &cause] {
+      hasher.update(part.as_bytes());
+      hasher.update([0]);
+    }
+    hex::encode(&hasher.finalize()[..8])
+  }
+}
+
+fn cause_of(err: Option<&(dyn Error + 'static)>) -> String {
+  err.map(error_chain).unwrap_or_default().join(": ")
+}
+
+impl From<&AppError> for ErrorReport {
+  fn from(err: &AppError) -> Self {
+    let source = err.source();
+    Self {
+      id: err.id.clone(),
+      path: err.path.clone(),
+      err_type: source.and_then(error_type_of).map(|t| t.to_string()).unwrap_or_default(),
+      cause: cause_of(source),
+      request_id: err.request_id.clone().unwrap_or_else(|| err.ctx.request_id.clone()),
+    }
+  }
+}
+
+impl From<&InternalError> for ErrorReport {
+  fn from(err: &InternalError) -> Self {
+    Self {
+      id: err.msg.clone(),
+      path: err.path.clone(),
+      err_type: err.err_type.to_string(),
+      cause: cause_of(Some(err.err.as_ref())),
+      request_id: String::new(),
+    }
+  }
+}
+
+// a value after a colon (`for type integer: "abc"`), or a quoted identifier or value
+static QUOTED: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"(: )?"([^"]*)"|'[^']*'"#).unwrap());
+
+static IDENTIFIER: LazyLock<Regex> =
+  LazyLock::new(|| Regex::new(r"^[A-Za-z_][A-Za-z0-9_$.]{0,62}$").unwrap());
+
+static VARIABLE_PARTS: LazyLock<Vec<(Regex, &'static str)>> = LazyLock::new(|| {
+  [
+    // the values of the database details: Key (sku)=(A-1)
+    (r"=\([^)]*\)", "=(?)"),
+    (r"(?i)\b[0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12}\b", "?"),
+    (r"\b[0-9A-HJKMNP-TV-Z]{26}\b", "?"),
+    (r"(?i)\b0x[0-9a-f]+\b", "?"),
+    // not the digits of a name, e.g. `orders_2024`
+    (r"(^|[^A-Za-z0-9_])\d+(\.\d+)?", "${1}N"),
+  ]
+  .into_iter()
+  .map(|(re, rep)| (Regex::new(re).unwrap(), rep))
+  .collect()
+});
+
+/// Replaces the values in an error message (quoted values, uuids, ulids, numbers) with
+/// placeholders. The quoted identifiers (`relation "orders"`, `constraint "users_email_key"`)
+/// are kept, so the errors of different tables or constraints aren't merged.
+pub fn normalize_cause(cause: &str) -> String {
+  let mask = |s: &str| {
+    VARIABLE_PARTS.iter().fold(s.to_string(), |s, (re, rep)| re.replace_all(&s, *rep).into_owned())
+  };
+
+  let mut out = String::with_capacity(cause.len());
+  let mut last = 0;
+  for caps in QUOTED.captures_iter(cause) {
+    let m = caps.get(0).unwrap();
+    out.push_str(&mask(&cause[last..m.start()]));
+    last = m.end();
+    match (caps.get(1), caps.get(2)) {
+      (None, Some(quoted)) if IDENTIFIER.is_match(quoted.as_str()) => out.push_str(m.as_str()),
+      (Some(colon), _) => {
+        out.push_str(colon.as_str());
+        out.push('?');
+      }
+      _ => out.push('?'),
+    }
+  }
+  out.push_str(&mask(&cause[last..]));
+  out
+}
+
+/// The errors with the same fingerprint in a window
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ErrorSummary {
+  pub fingerprint: String,
+  pub id: String,
+  pub path: String,
+  pub err_type: String,
+  /// The cause of the first error of the window
+  pub cause: String,
+  pub count: u64,
+  /// Unix milliseconds
+  pub first_seen: u64,
+  pub last_seen: u64,
+  /// The request of the last error of the window
+  pub request_id: String,
+}
+
+/// Where an `ErrorSink` sends the summaries of a window. `flush` may block, the sink calls
+/// it on the blocking threads when it runs in a tokio runtime.
+pub trait ErrorSinkBackend: Send + Sync {
+  fn flush(&self, summaries: &[ErrorSummary]) -> Result<(), BoxedErr>;
+}
+
+/// Logs a `tracing` event per summary
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LogBackend;
+
+impl ErrorSinkBackend for LogBackend {
+  fn flush(&self, summaries: &[ErrorSummary]) -> Result<(), BoxedErr> {
+    for s in summaries {
+      tracing::error!(
+        fingerprint = %s.fingerprint,
+        id = %s.id,
+        path = %s.path,
+        err_type = %s.err_type,
+        cause = %s.cause,
+        count = s.count,
+        first_seen = s.first_seen,
+        last_seen = s.last_seen,
+        request_id = %s.request_id,
+        "errors summary"
+      );
+    }
+    Ok(())
+  }
+}
+
+/// Appends the summaries to a file, one JSON object per line
+#[derive(Debug, Clone)]
+pub struct FileBackend {
+  pub path: PathBuf,
+}
+
+impl FileBackend {
+  pub fn new(path: impl Into<PathBuf>) -> Self {
+    Self { path: path.into() }
+  }
+}
+
+impl ErrorSinkBackend for FileBackend {
+  fn flush(&self, summaries: &[ErrorSummary]) -> Result<(), BoxedErr> {
+    let mut lines = String::new();
+    for s in summaries {
+      lines.push_str(&serde_json::to_string(s)?);
+      lines.push('\n');
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+    file.write_all(lines.as_bytes())?;
+    Ok(())
+  }
+}
+
+/// Keeps the flushed summaries, for tests
+#[derive(Debug, Default)]
+pub struct MemoryBackend {
+  summaries: Mutex<Vec<ErrorSummary>>,
+}
+
+impl MemoryBackend {
+  pub fn summaries(&self) -> Vec<ErrorSummary> {
+    self.summaries.lock().unwrap().clone()
+  }
+}
+
+impl ErrorSinkBackend for MemoryBackend {
+  fn flush(&self, summaries: &[ErrorSummary]) -> Result<(), BoxedErr> {
+    self.summaries.lock().unwrap().extend_from_slice(summaries);
+    Ok(())
+  }
+}
+
+/// The default of `ErrorSink::with_max_summaries`
+pub const DEFAULT_MAX_SUMMARIES: usize = 1000;
+
+/// The fingerprint of the summary counting the errors past `ErrorSink::with_max_summaries`
+pub const OVERFLOW_FINGERPRINT: &str = "overflow";
+
+struct Window {
+  start: Instant,
+  summaries: HashMap<String, ErrorSummary>,
+}
+
+/// Counts the errors by fingerprint and sends a summary of each to the backend once per
+/// window, instead of every single error.
+///
+/// A window is flushed by the first `record` after it ends, or on time by `spawn_flusher`.
+pub struct ErrorSink {
+  window: Duration,
+  max_summaries: usize,
+  backend: Arc<dyn ErrorSinkBackend>,
+  current: Mutex<Window>,
+}
+
+impl ErrorSink {
+  pub fn new(window: Duration, backend: Arc<dyn ErrorSinkBackend>) -> Self {
+    let current = Mutex::new(Window { start: Instant::now(), summaries: HashMap::new() });
+    Self { window, max_summaries: DEFAULT_MAX_SUMMARIES, backend, current }
+  }
+
+  /// Sets how many fingerprints a window keeps, the errors with other fingerprints are
+  /// counted in a single `OVERFLOW_FINGERPRINT` summary
+  pub fn with_max_summaries(mut self, max_summaries: usize) -> Self {
+    self.max_summaries = max_summaries;
+    self
+  }
+
+  pub fn record(&self, report: impl Into<ErrorReport>) {
+    let mut report = report.into();
+    let mut fingerprint = report.fingerprint();
+    let now = now_millis();
+
+    let mut current = self.current.lock().unwrap();
+    let full = current.summaries.len() >= self.max_summaries;
+    if full && !current.summaries.contains_key(&fingerprint) {
+      fingerprint = OVERFLOW_FINGERPRINT.to_string();
+      report = ErrorReport {
+        id: "error_sink.overflow".to_string(),
+        cause: format!("more than {} distinct errors in the window", self.max_summaries),
+        request_id: report.request_id,
+        ..Default::default()
+      };
+    }
+    let summary = current.summaries.entry(fingerprint.clone()).or_insert_with(|| ErrorSummary {
+      fingerprint,
+      id: report.id,
+      path: report.path,
+      err_type: report.err_type,
+      cause: report.cause,
+      count: 0,
+      first_seen: now,
+      last_seen: now,
+      request_id: String::new(),
+    });
+    summary.count += 1;
+    summary.last_seen = now;
+    summary.request_id = report.request_id;
+
+    if current.start.elapsed() >= self.window {
+      let summaries = Self::take(&mut current);
+      drop(current);
+      // `record` is called from async code, which mustn't wait on the backend
+      match Handle::try_current() {
+        Ok(runtime) => {
+          let backend = self.backend.clone();
+          runtime.spawn_blocking(move || send(backend.as_ref(), summaries));
+        }
+        Err(_) => send(self.backend.as_ref(), summaries),
+      }
+    }
+  }
+
+  /// Sends the summaries of the current window and starts a new one. It blocks until the
+  /// backend is done, async code should use `spawn_flusher` instead.
+  pub fn flush(&self) {
+    let summaries = Self::take(&mut self.current.lock().unwrap());
+    send(self.backend.as_ref(), summaries);
+  }
+
+  /// Flushes every window until `cancel` is cancelled, then flushes a last time
+  pub fn spawn_flusher(self: Arc<Self>, cancel: CancellationToken) -> JoinHandle<()> {
+    tokio::spawn(async move {
+      let flush = |sink: Arc<Self>| tokio::task::spawn_blocking(move || sink.flush());
+      let mut interval = tokio::time::interval(self.window.max(Duration::from_millis(1)));
+      interval.tick().await;
+      loop {
+        tokio::select! {
+          _ = cancel.cancelled() => break,
+          _ = interval.tick() => {
+            let _ = flush(self.clone()).await;
+          }
+        }
+      }
+      let _ = flush(self).await;
+    })
+  }
+
+  fn take(window: &mut Window) -> Vec<ErrorSummary> {
+    window.start = Instant::now();
+    let mut summaries: Vec<_> = window.summaries.drain().map(|(_, s)| s).collect();
+    summaries.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.fingerprint.cmp(&b.fingerprint)));
+    summaries
+  }
+}
+
+fn send(backend: &dyn ErrorSinkBackend, summaries: Vec<ErrorSummary>) {
+  if summaries.is_empty() {
+    return;
+  }
+  if let Err(err) = backend.flush(&summaries) {
+    tracing::warn!(error = %err, count = summaries.len(), "failed to flush the errors summaries");
+  }
+}
+
+fn now_millis() -> u64 {
+  SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::models::errors::ErrorType;
+
+  fn internal(msg: &str) -> InternalError {
+    InternalError::new(
+      "ProductsStore.create".into(),
+      msg.to_string().into(),
+      ErrorType::UniqueViolation,
+      false,
+      "insert failed".into(),
+    )
+  }
+
+  #[test]
+  fn test_fingerprint() {
+    let a = ErrorReport::from(&internal(r#"Key (sku)=(A-1) already exists, id 12"#));
+    let b = ErrorReport::from(&internal(r#"Key (sku)=(B-22) already exists, id 345"#));
+    let c = ErrorReport::from(&internal("connection reset"));
+    assert_eq!(a.fingerprint(), b.fingerprint());
+    assert_ne!(a.fingerprint(), c.fingerprint());
+
+    // the message of an `InternalError` is its id
+    let report = |msg: &str| {
+      let path = "ProductsStore.get".to_string();
+      let err = InternalError::new(path, "no rows".into(), ErrorType::NotFound, false, msg.into());
+      ErrorReport::from(&err)
+    };
+    assert_eq!(
+      report("product 12 not found").fingerprint(),
+      report("product 345 not found").fingerprint()
+    );
+    assert_eq!(
+      normalize_cause(r#"user 01ARZ3NDEKTSV4RRFFQ69G5FAV not found in "users" after 3.5s"#),
+      r#"user ? not found in "users" after Ns"#
+    );
+
+    // the identifiers are kept, the literal values masked
+    let unique = |constraint: &str| {
+      format!(
+        r#"duplicate key value violates unique constraint "{constraint}": Key (email)=(a@b.c)"#
+      )
+    };
+    assert_ne!(
+      normalize_cause(&unique("users_email_key")),
+      normalize_cause(&unique("orders_pkey"))
+    );
+    assert_eq!(
+      normalize_cause(r#"invalid input syntax for type integer: "abc" in "orders_2024", 'x y'"#),
+      r#"invalid input syntax for type integer: ? in "orders_2024", ?"#
+    );
+    assert_eq!(normalize_cause(r#"value "not an identifier" is too long"#), "value ? is too long");
+  }
+
+  #[test]
+  fn test_error_sink() {
+    let backend = Arc::new(MemoryBackend::default());
+    let sink = ErrorSink::new(Duration::from_secs(60), backend.clone());
+    for i in 0..100 {
+      sink.record(&internal(&format!("Key (sku)=(A-{i}) already exists")));
+    }
+    sink.record(&internal("connection reset"));
+    assert!(backend.summaries().is_empty());
+
+    sink.flush();
+    let summaries = backend.summaries();
+    assert_eq!(summaries.len(), 2);
+    assert_eq!(summaries[0].count, 100);
+    assert_eq!(summaries[0].cause, "Key (sku)=(A-0) already exists");
+    assert_eq!(summaries[0].err_type, "unique_violation");
+    assert_eq!(summaries[1].count, 1);
+
+    // a new window
+    sink.flush();
+    assert_eq!(backend.summaries().len(), 2);
+  }
+
+  #[test]
+  fn test_error_sink_max_summaries() {
+    let backend = Arc::new(MemoryBackend::default());
+    let sink = ErrorSink::new(Duration::from_secs(60), backend.clone()).with_max_summaries(2);
+    for msg in ["a", "b", "c", "d", "a"] {
+      sink.record(&internal(msg));
+    }
+    sink.flush();
+
+    let summaries = backend.summaries();
+    let counts: Vec<_> = summaries.iter().map(|s| (s.cause.as_str(), s.count)).collect();
+    assert_eq!(counts, vec![("a", 2), ("more than 2 distinct errors in the window", 2), ("b", 1)]);
+    assert_eq!(summaries[1].fingerprint, OVERFLOW_FINGERPRINT);
+  }
+
+  #[test]
+  fn test_error_sink_window() {
+    let backend = Arc::new(MemoryBackend::default());
+    let sink = ErrorSink::new(Duration::ZERO, backend.clone());
+    sink.record(&internal("a"));
+    sink.record(&internal("a"));
+    assert_eq!(backend.summaries().iter().map(|s| s.count).collect::<Vec<_>>(), vec![1, 1]);
+  }
+
+  #[tokio::test]
+  async fn test_spawn_flusher() {
+    let backend = Arc::new(MemoryBackend::default());
+    let sink = Arc::new(ErrorSink::new(Duration::from_secs(60), backend.clone()));
+    let cancel = CancellationToken::new();
+    let flusher = sink.clone().spawn_flusher(cancel.clone());
+    sink.record(&internal("a"));
+    assert!(backend.summaries().is_empty());
+
+    cancel.cancel();
+    flusher.await.unwrap();
+    assert_eq!(backend.summaries().len(), 1);
+  }
+
+  #[test]
+  fn test_file_backend() {
+    let path = std::env::temp_dir().join(format!("errors-{}.jsonl", ulid::Ulid::new()));
+    let sink = ErrorSink::new(Duration::from_secs(60), Arc::new(FileBackend::new(&path)));
+    sink.record(&internal("a"));
+    sink.flush();
+    sink.record(&internal("b"));
+    sink.flush();
+
+    let content = std::fs::read_to_string(&path).unwrap();
+    let lines: Vec<ErrorSummary> =
+      content.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+    assert_eq!(lines.len(), 2);
+    assert_eq!((lines[0].cause.as_str(), lines[1].cause.as_str()), ("a", "b"));
+    std::fs::remove_file(path).unwrap();
+  }
+}
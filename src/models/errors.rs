@@ -1,14 +1,24 @@
-use std::{collections::HashMap, error::Error, fmt, sync::Arc};
+use std::{
+  collections::HashMap,
+  error::Error,
+  fmt,
+  sync::{
+    Arc, LazyLock, RwLock,
+    atomic::{AtomicBool, Ordering},
+  },
+};
 
 use derive_more::Display;
+use http::StatusCode;
 use megacommerce_proto::{AppError as AppErrorProto, NestedStringMap, StringMap};
+use prost::Message;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use tonic::Code;
+use tonic::{Code, Status};
 
 use super::{
   context::Context,
-  translate::{tr, TranslateFunc},
+  translate::{default_translator, tr_ctx, TranslateFunc, TranslationError, Translator},
 };
 
 pub type BoxedErr = Box<dyn Error + Sync + Send>;
@@ -48,6 +58,15 @@ pub enum ErrorType {
   Base64Invalid,
   RegexInvalid,
   InvalidNumber,
+  CheckViolation,
+  ExclusionViolation,
+  SerializationFailure,
+  DeadlockDetected,
+  LockNotAvailable,
+  QueryCanceled,
+  InvalidTextRepresentation,
+  NumericValueOutOfRange,
+  StringDataRightTruncation,
 }
 
 impl fmt::Display for ErrorType {
@@ -79,10 +98,105 @@ impl fmt::Display for ErrorType {
       ErrorType::Base64Invalid => write!(f, "base64_invalid"),
       ErrorType::RegexInvalid => write!(f, "regex_invalid"),
       ErrorType::InvalidNumber => write!(f, "invalid_number"),
+      ErrorType::CheckViolation => write!(f, "check_violation"),
+      ErrorType::ExclusionViolation => write!(f, "exclusion_violation"),
+      ErrorType::SerializationFailure => write!(f, "serialization_failure"),
+      ErrorType::DeadlockDetected => write!(f, "deadlock_detected"),
+      ErrorType::LockNotAvailable => write!(f, "lock_not_available"),
+      ErrorType::QueryCanceled => write!(f, "query_canceled"),
+      ErrorType::InvalidTextRepresentation => write!(f, "invalid_text_representation"),
+      ErrorType::NumericValueOutOfRange => write!(f, "numeric_value_out_of_range"),
+      ErrorType::StringDataRightTruncation => write!(f, "string_data_right_truncation"),
     }
   }
 }
 
+impl ErrorType {
+  /// The status code of the errors of this type. Database and upstream failures that
+  /// the caller can't act on are `Internal` or `Unavailable`.
+  pub fn grpc_code(&self) -> Code {
+    match self {
+      ErrorType::NoRows | ErrorType::NotFound => Code::NotFound,
+      ErrorType::UniqueViolation | ErrorType::ExclusionViolation => Code::AlreadyExists,
+      ErrorType::ForeignKeyViolation => Code::FailedPrecondition,
+      ErrorType::SerializationFailure
+      | ErrorType::DeadlockDetected
+      | ErrorType::LockNotAvailable => Code::Aborted,
+      ErrorType::NotNullViolation
+      | ErrorType::CheckViolation
+      | ErrorType::InvalidTextRepresentation
+      | ErrorType::NumericValueOutOfRange
+      | ErrorType::StringDataRightTruncation
+      | ErrorType::MissingField
+      | ErrorType::InvalidData
+      | ErrorType::JsonUnmarshal
+      | ErrorType::Base64Invalid
+      | ErrorType::InvalidNumber => Code::InvalidArgument,
+      ErrorType::Privileges => Code::PermissionDenied,
+      ErrorType::Connection | ErrorType::DBConnectionError | ErrorType::HttpRequestError => {
+        Code::Unavailable
+      }
+      ErrorType::TimedOut | ErrorType::QueryCanceled => Code::DeadlineExceeded,
+      ErrorType::JsonMarshal
+      | ErrorType::Internal
+      | ErrorType::DBSelectError
+      | ErrorType::DBInsertError
+      | ErrorType::DBUpdateError
+      | ErrorType::DBDeleteError
+      | ErrorType::ConfigError
+      | ErrorType::HttpResponseError
+      | ErrorType::HttpEmptyResponse
+      | ErrorType::TaskFailed
+      | ErrorType::RegexInvalid => Code::Internal,
+    }
+  }
+
+  /// The HTTP status of the errors of this type, the one of `grpc_code` except for the
+  /// failed requests to other services, which are `502 Bad Gateway`
+  pub fn http_status(&self) -> StatusCode {
+    match self {
+      ErrorType::HttpRequestError | ErrorType::HttpResponseError | ErrorType::HttpEmptyResponse => {
+        StatusCode::BAD_GATEWAY
+      }
+      _ => grpc_code_to_http_status(self.grpc_code()),
+    }
+  }
+
+  /// Whether the same operation may succeed if retried: lost connections, timeouts, and
+  /// transactions aborted by concurrent ones. A canceled query isn't, it was canceled by a
+  /// statement timeout (and would likely time out again) or on purpose.
+  pub fn is_retryable(&self) -> bool {
+    matches!(
+      self,
+      ErrorType::Connection
+        | ErrorType::DBConnectionError
+        | ErrorType::TimedOut
+        | ErrorType::HttpRequestError
+        | ErrorType::SerializationFailure
+        | ErrorType::DeadlockDetected
+        | ErrorType::LockNotAvailable
+    )
+  }
+}
+
+/// The HTTP status of a gRPC status code, as mapped by the gRPC HTTP gateways
+pub fn grpc_code_to_http_status(code: Code) -> StatusCode {
+  match code {
+    Code::Ok => StatusCode::OK,
+    Code::Cancelled => StatusCode::from_u16(499).unwrap(),
+    Code::InvalidArgument | Code::FailedPrecondition | Code::OutOfRange => StatusCode::BAD_REQUEST,
+    Code::DeadlineExceeded => StatusCode::GATEWAY_TIMEOUT,
+    Code::NotFound => StatusCode::NOT_FOUND,
+    Code::AlreadyExists | Code::Aborted => StatusCode::CONFLICT,
+    Code::PermissionDenied => StatusCode::FORBIDDEN,
+    Code::Unauthenticated => StatusCode::UNAUTHORIZED,
+    Code::ResourceExhausted => StatusCode::TOO_MANY_REQUESTS,
+    Code::Unimplemented => StatusCode::NOT_IMPLEMENTED,
+    Code::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+    Code::Unknown | Code::Internal | Code::DataLoss => StatusCode::INTERNAL_SERVER_ERROR,
+  }
+}
+
 #[derive(Debug)]
 pub struct SimpleError {
   pub message: String,
@@ -106,13 +220,74 @@ impl InternalError {
   }
 }
 
+impl InternalError {
+  /// Emits the error as a structured `tracing` event, with the request of `ctx` if given
+  pub fn log(&self, ctx: Option<&Context>) {
+    let (request_id, user_id) = ctx.map(request_fields).unwrap_or_default();
+    let cause_chain = error_chain(self.err.as_ref()).join(": ");
+    tracing::error!(
+      request_id,
+      user_id,
+      err_type = %self.err_type,
+      path = %self.path,
+      temp = self.temp,
+      msg = %self.msg,
+      cause_chain,
+      "internal error"
+    );
+  }
+}
+
+/// The request id and user id of `ctx` to log, never the session token
+pub(crate) fn request_fields(ctx: &Context) -> (&str, &str) {
+  (&ctx.request_id, &ctx.session.user_id)
+}
+
+/// The messages of `err` and of its sources, outermost first
+pub fn error_chain(err: &(dyn Error + 'static)) -> Vec<String> {
+  let mut chain = vec![];
+  let mut next = Some(err);
+  while let Some(e) = next {
+    chain.push(e.to_string());
+    next = e.source();
+  }
+  chain
+}
+
+/// The type of the first `InternalError` or `DBError` in the chain of `err`
+pub fn error_type_of<'a>(err: &'a (dyn Error + 'static)) -> Option<&'a ErrorType> {
+  let mut next = Some(err);
+  while let Some(e) = next {
+    if let Some(e) = e.downcast_ref::<InternalError>() {
+      return Some(&e.err_type);
+    }
+    #[cfg(any(feature = "store", feature = "all"))]
+    if let Some(e) = e.downcast_ref::<crate::store::errors::DBError>() {
+      return Some(&e.err_type);
+    }
+    next = e.source();
+  }
+  None
+}
+
 impl Error for InternalError {
   fn source(&self) -> Option<&(dyn Error + 'static)> {
     Some(self.err.as_ref())
   }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Separates the details of `AppErrorProto::detailed_error` from the `AppErrorMetadata`
+/// after them. JSON escapes control characters, so it can't be part of the metadata.
+const APP_ERROR_METADATA_SEPARATOR: char = '\u{1E}';
+
+/// A link of the cause chain of an `AppError`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AppErrorCause {
+  pub path: String,
+  pub id: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct AppErrorError {
   pub id: String,
   pub params: Option<HashMap<String, Value>>,
@@ -125,6 +300,56 @@ pub struct AppErrorErrors {
   pub errors_nested_internal: Option<HashMap<String, HashMap<String, AppErrorError>>>,
 }
 
+/// What `AppErrorProto` has no field for: the params and the field error ids the error is
+/// translated from, and its cause chain. `to_proto` encodes it at the end of the internal
+/// `detailed_error`, so a calling service can rebuild the exact `AppError` and translate it
+/// again, whether the proto is sent in a response or in a status.
+///
+/// `sanitize_app_error` strips it with the details, it never reaches clients.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AppErrorMetadata {
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub tr_params: OptionalParams,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub errors_internal: Option<HashMap<String, AppErrorError>>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub errors_nested_internal: Option<HashMap<String, HashMap<String, AppErrorError>>>,
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub causes: Vec<AppErrorCause>,
+}
+
+impl AppErrorMetadata {
+  pub fn is_empty(&self) -> bool {
+    self.tr_params.is_none()
+      && self.errors_internal.is_none()
+      && self.errors_nested_internal.is_none()
+      && self.causes.is_empty()
+  }
+
+  /// `details` followed by the encoded metadata, `details` alone if it's empty
+  pub fn append_to(&self, details: &str) -> String {
+    if self.is_empty() {
+      return details.to_string();
+    }
+    // a Value serializes its maps sorted, so the same error always gives the same string
+    match serde_json::to_value(self).and_then(|v| serde_json::to_string(&v)) {
+      Ok(json) => format!("{details}{APP_ERROR_METADATA_SEPARATOR}{json}"),
+      Err(_) => details.to_string(),
+    }
+  }
+
+  /// Splits a `detailed_error` made by `append_to` into the details and the metadata
+  pub fn split_from(detailed_error: &str) -> (&str, Option<Self>) {
+    let Some((details, json)) = detailed_error.rsplit_once(APP_ERROR_METADATA_SEPARATOR) else {
+      return (detailed_error, None);
+    };
+    match serde_json::from_str(json) {
+      Ok(metadata) => (details, Some(metadata)),
+      Err(_) => (detailed_error, None),
+    }
+  }
+}
+
 #[derive(Debug)]
 pub struct AppError {
   pub ctx: Arc<Context>,
@@ -141,6 +366,11 @@ pub struct AppError {
   pub errors_nested: Option<HashMap<String, HashMap<String, String>>>,
   pub errors_internal: Option<HashMap<String, AppErrorError>>,
   pub errors_nested_internal: Option<HashMap<String, HashMap<String, AppErrorError>>>,
+  /// The errors this one was created from, nearest first, e.g. the error of a downstream
+  /// service that this service forwards
+  pub causes: Vec<AppErrorCause>,
+  /// Set once `log` ran, so the conversions log the error once
+  logged: AtomicBool,
 }
 
 impl AppError {
@@ -151,19 +381,60 @@ impl AppError {
     id_params: OptionalParams,
     details: impl Into<String>,
     status_code: i32,
-    mut errors: Option<AppErrorErrors>,
+    errors: Option<AppErrorErrors>,
   ) -> Self {
-    if errors.is_none() {
-      errors =
-        Some(AppErrorErrors { err: None, errors_internal: None, errors_nested_internal: None });
-    };
+    let mut err = Self::untranslated(ctx, path, id, id_params, details, status_code, errors);
 
-    let unwraped = {
-      let e = errors.unwrap();
-      (e.err, e.errors_internal, e.errors_nested_internal)
-    };
+    let timezone = err.ctx.timezone.clone();
+    let boxed_tr = Box::new(move |lang: &str, id: &str, params: &HashMap<String, Value>| {
+      let params_option = if params.is_empty() { None } else { Some(params.clone()) };
+      default_translator()
+        .and_then(|t| t.tr_with_timezone(lang, &timezone, id, params_option))
+        .map_err(|e| Box::new(e) as Box<dyn Error>)
+    });
+
+    err.translate(Some(boxed_tr));
+    err
+  }
+
+  /// Same as `new`, but translates with `translator` instead of the default translator
+  #[allow(clippy::too_many_arguments)]
+  pub fn new_with_translator(
+    translator: &Translator,
+    ctx: Arc<Context>,
+    path: impl Into<String>,
+    id: impl Into<String>,
+    id_params: OptionalParams,
+    details: impl Into<String>,
+    status_code: i32,
+    errors: Option<AppErrorErrors>,
+  ) -> Self {
+    let mut err = Self::untranslated(ctx, path, id, id_params, details, status_code, errors);
+    err.translate_with(translator);
+    err
+  }
 
-    let mut err = Self {
+  /// Starts building an error, see `AppErrorBuilder`
+  pub fn builder(
+    ctx: Arc<Context>,
+    path: impl Into<String>,
+    id: impl Into<String>,
+  ) -> AppErrorBuilder {
+    AppErrorBuilder::new(ctx, path, id)
+  }
+
+  fn untranslated(
+    ctx: Arc<Context>,
+    path: impl Into<String>,
+    id: impl Into<String>,
+    id_params: OptionalParams,
+    details: impl Into<String>,
+    status_code: i32,
+    errors: Option<AppErrorErrors>,
+  ) -> Self {
+    let errors = errors.unwrap_or_default();
+
+    Self {
       ctx,
       id: id.into(),
       path: path.into(),
@@ -173,20 +444,14 @@ impl AppError {
       status_code,
       tr_params: id_params,
       skip_translation: false,
-      error: unwraped.0,
+      error: errors.err,
       errors: None,
       errors_nested: None,
-      errors_internal: unwraped.1,
-      errors_nested_internal: unwraped.2,
-    };
-
-    let boxed_tr = Box::new(|lang: &str, id: &str, params: &HashMap<String, serde_json::Value>| {
-      let params_option = if params.is_empty() { None } else { Some(params.clone()) };
-      tr(lang, id, params_option).map_err(|e| Box::new(e) as Box<dyn Error>)
-    });
-
-    err.translate(Some(boxed_tr));
-    err
+      errors_internal: errors.errors_internal,
+      errors_nested_internal: errors.errors_nested_internal,
+      causes: vec![],
+      logged: AtomicBool::new(false),
+    }
   }
 
   pub fn error_string(&self) -> String {
@@ -220,7 +485,7 @@ impl AppError {
     if let Some(tf) = tf {
       let empty = HashMap::new();
       let params = self.tr_params.as_ref().unwrap_or(&empty);
-      if let Ok(translated) = tf(&self.ctx.accept_language, &self.id, params) {
+      if let Ok(translated) = tf(self.ctx.language(), &self.id, params) {
         self.message = translated;
         return;
       }
@@ -228,6 +493,17 @@ impl AppError {
     self.message = self.id.clone();
   }
 
+  /// Translates the message with `translator`, falling back to the id on failure
+  pub fn translate_with(&mut self, translator: &Translator) {
+    if self.skip_translation {
+      return;
+    }
+
+    let params = self.tr_params.as_ref().filter(|p| !p.is_empty());
+    self.message =
+      translator.tr_ctx(&self.ctx, &self.id, params).unwrap_or_else(|_| self.id.clone());
+  }
+
   pub fn unwrap(&self) -> Option<&(dyn Error + Send + Sync)> {
     self.error.as_deref()
   }
@@ -258,22 +534,105 @@ impl AppError {
       errors_nested: None,
       errors_internal: None,
       errors_nested_internal: None,
+      causes: vec![],
+      logged: AtomicBool::new(false),
+    }
+  }
+
+  /// Adds `cause` and its own causes to the cause chain
+  pub fn with_cause(mut self, cause: &AppError) -> Self {
+    self.causes.extend(cause.cause_chain());
+    self
+  }
+
+  /// This error followed by its causes
+  fn cause_chain(&self) -> impl Iterator<Item = AppErrorCause> + '_ {
+    let this = AppErrorCause { path: self.path.clone(), id: self.id.clone() };
+    std::iter::once(this).chain(self.causes.iter().cloned())
+  }
+
+  /// Emits the error as a structured `tracing` event: the request, the `ErrorType` of the
+  /// source error, the status code and the cause chain. Server errors are logged at the
+  /// error level, the others at the warn level. The details are left out, they may hold
+  /// the values of the user (`Key (email)=(...)`).
+  ///
+  /// Done by the first conversion to a response (`to_proto`, `to_status`,
+  /// `From<AppError>`), the later ones don't log again.
+  pub fn log(&self) {
+    self.logged.store(true, Ordering::Relaxed);
+    let (request_id, user_id) = request_fields(&self.ctx);
+    let request_id = self.request_id.as_deref().filter(|id| !id.is_empty()).unwrap_or(request_id);
+    let err_type = self.source().and_then(error_type_of).map(|t| t.to_string()).unwrap_or_default();
+    let cause_chain = self.source().map(error_chain).unwrap_or_default().join(": ");
+    let causes: Vec<_> = self.causes.iter().map(|c| format!("{} {}", c.path, c.id)).collect();
+    let causes = causes.join(", ");
+
+    macro_rules! event {
+      ($level:expr) => {
+        tracing::event!(
+          $level,
+          request_id,
+          user_id,
+          err_type,
+          path = %self.path,
+          id = %self.id,
+          status_code = self.status_code,
+          cause_chain,
+          causes,
+          "{}",
+          self.message
+        )
+      };
+    }
+    match Code::from(self.status_code) {
+      Code::Internal | Code::Unknown | Code::DataLoss | Code::Unavailable => {
+        event!(tracing::Level::ERROR)
+      }
+      _ => event!(tracing::Level::WARN),
     }
   }
 
-  /// Convert to proto-generated struct
+  /// Convert to proto-generated struct, logging the error if it wasn't yet
   pub fn to_proto(&self) -> AppErrorProto {
-    let mut nested = HashMap::new();
+    self.log_once();
+    self.to_proto_inner(|id, params| tr_ctx(&self.ctx, id, params))
+  }
+
+  /// Same as `to_proto`, but translates the field errors with `translator`
+  pub fn to_proto_with(&self, translator: &Translator) -> AppErrorProto {
+    self.log_once();
+    self.to_proto_inner(|id, params| translator.tr_ctx(&self.ctx, id, params))
+  }
+
+  fn log_once(&self) {
+    if !self.logged.load(Ordering::Relaxed) {
+      self.log();
+    }
+  }
+
+  fn to_proto_inner<F>(&self, tr_fn: F) -> AppErrorProto
+  where
+    F: Fn(&str, OptionalParams) -> Result<String, TranslationError>,
+  {
+    let mut nested: HashMap<String, StringMap> = HashMap::new();
     if let Some(errors) = &self.errors_nested {
       for (k, v) in errors {
         nested.insert(k.clone(), StringMap { values: v.clone() });
       }
     }
+    for (parent, fields) in self.errors_nested_internal.iter().flatten() {
+      let values = &mut nested.entry(parent.clone()).or_default().values;
+      for (field, value) in fields {
+        let result = tr_fn(&value.id, value.params.clone()).unwrap_or_default();
+        values.insert(field.clone(), result);
+      }
+    }
 
-    let mut errors: HashMap<String, String> = HashMap::new();
+    // already translated errors (e.g. received from another service) are kept, unless
+    // they are translated again from their id
+    let mut errors: HashMap<String, String> = self.errors.clone().unwrap_or_default();
     for (key, value) in self.errors_internal.clone().unwrap_or_default().iter() {
-      let result =
-        tr(&self.ctx.accept_language(), &value.id, value.params.clone()).unwrap_or_default();
+      let result = tr_fn(&value.id, value.params.clone()).unwrap_or_default();
       errors.insert(key.to_string(), result);
     }
 
@@ -281,8 +640,8 @@ impl AppError {
       id: self.id.clone(),
       r#where: self.path.clone(),
       message: self.message.clone(),
-      detailed_error: self.detailes.clone(),
-      status_code: self.status_code as i32,
+      detailed_error: self.metadata().append_to(&self.detailes),
+      status_code: self.status_code,
       skip_translation: self.skip_translation,
       request_id: self.request_id.clone().unwrap_or_default(),
       errors: Some(StringMap { values: errors }),
@@ -290,6 +649,72 @@ impl AppError {
     }
   }
 
+  /// What the proto of this error has no field for, see `AppErrorMetadata`
+  pub fn metadata(&self) -> AppErrorMetadata {
+    AppErrorMetadata {
+      tr_params: self.tr_params.clone().filter(|p| !p.is_empty()),
+      errors_internal: self.errors_internal.clone().filter(|e| !e.is_empty()),
+      errors_nested_internal: self.errors_nested_internal.clone().filter(|e| !e.is_empty()),
+      causes: self.causes.clone(),
+    }
+  }
+
+  /// Restores what `metadata` carried, see `app_error_from_proto_app_error`
+  pub fn with_metadata(mut self, metadata: AppErrorMetadata) -> Self {
+    self.tr_params = metadata.tr_params;
+    self.errors_internal = metadata.errors_internal;
+    self.errors_nested_internal = metadata.errors_nested_internal;
+    self.causes = metadata.causes;
+    self
+  }
+
+  /// Converts to a gRPC status for clients: the code from `status_code`, the translated
+  /// message, and the `AppErrorProto` (field errors, request id, ...) encoded as the status
+  /// details, all passed through `sanitize_app_error` first.
+  pub fn to_status(&self) -> Status {
+    self.status_from_proto(sanitize_app_error(&self.to_proto()))
+  }
+
+  /// Same as `to_status`, but translates the field errors with `translator`
+  pub fn to_status_with(&self, translator: &Translator) -> Status {
+    self.status_from_proto(sanitize_app_error(&self.to_proto_with(translator)))
+  }
+
+  /// Same as `to_status` but not sanitized, for service to service calls: the details,
+  /// the path and what `AppErrorMetadata` carries are kept, `AppError::from_status`
+  /// recovers the exact error on the calling side.
+  pub fn to_internal_status(&self) -> Status {
+    self.status_from_proto(self.to_proto())
+  }
+
+  /// Same as `to_internal_status`, but translates the field errors with `translator`
+  pub fn to_internal_status_with(&self, translator: &Translator) -> Status {
+    self.status_from_proto(self.to_proto_with(translator))
+  }
+
+  fn status_from_proto(&self, mut proto: AppErrorProto) -> Status {
+    if proto.request_id.is_empty() {
+      proto.request_id = self.ctx.request_id.clone();
+    }
+    let message = if proto.message.is_empty() { proto.id.clone() } else { proto.message.clone() };
+    Status::with_details(Code::from(proto.status_code), message, proto.encode_to_vec().into())
+  }
+
+  /// Recovers the `AppError` sent with `to_internal_status`, or what `to_status` left of
+  /// it. A status without (valid) details,
+  /// e.g. from a proxy or a transport failure, gives an error with its code and message.
+  pub fn from_status(ctx: Arc<Context>, status: &Status) -> Self {
+    match AppErrorProto::decode(status.details()) {
+      Ok(proto) if !status.details().is_empty() => app_error_from_proto_app_error(ctx, &proto),
+      _ => {
+        let mut err =
+          Self::untranslated(ctx, "", MSG_ID_ERR_INTERNAL, None, "", status.code().into(), None);
+        err.message = status.message().to_string();
+        err
+      }
+    }
+  }
+
   pub fn to_internal(self, ctx: Arc<Context>, path: String) -> Self {
     let errors = AppErrorErrors { err: self.error, ..Default::default() };
     Self::new(
@@ -304,16 +729,180 @@ impl AppError {
   }
 }
 
+/// Builds an `AppError` with named steps instead of the positional args of `AppError::new`:
+///
+/// ```ignore
+/// AppError::builder(ctx, "orders.get", "order.not_found")
+///   .param("id", order_id)
+///   .code(Code::NotFound)
+///   .field_error("id", "field.invalid", None)
+///   .build()
+/// ```
+///
+/// The status code defaults to `Code::Internal`. The message is translated once, by `build`.
+#[derive(Debug)]
+pub struct AppErrorBuilder {
+  ctx: Arc<Context>,
+  path: String,
+  id: String,
+  params: OptionalParams,
+  details: String,
+  code: Code,
+  source: OptionalErr,
+  errors: Option<HashMap<String, AppErrorError>>,
+  errors_nested: Option<HashMap<String, HashMap<String, AppErrorError>>>,
+  causes: Vec<AppErrorCause>,
+  skip_translation: bool,
+}
+
+impl AppErrorBuilder {
+  pub fn new(ctx: Arc<Context>, path: impl Into<String>, id: impl Into<String>) -> Self {
+    Self {
+      ctx,
+      path: path.into(),
+      id: id.into(),
+      params: None,
+      details: String::new(),
+      code: Code::Internal,
+      source: None,
+      errors: None,
+      errors_nested: None,
+      causes: vec![],
+      skip_translation: false,
+    }
+  }
+
+  /// Replaces the translation params of the message
+  pub fn params(mut self, params: HashMap<String, Value>) -> Self {
+    self.params = Some(params);
+    self
+  }
+
+  /// Adds one translation param of the message
+  pub fn param(mut self, key: impl Into<String>, value: impl Into<Value>) -> Self {
+    self.params.get_or_insert_default().insert(key.into(), value.into());
+    self
+  }
+
+  pub fn details(mut self, details: impl Into<String>) -> Self {
+    self.details = details.into();
+    self
+  }
+
+  pub fn code(mut self, code: Code) -> Self {
+    self.code = code;
+    self
+  }
+
+  /// The underlying error, returned by `AppError::unwrap` and `Error::source`
+  pub fn source(mut self, err: impl Into<BoxedErr>) -> Self {
+    self.source = Some(err.into());
+    self
+  }
+
+  /// Adds `cause` and its own causes to the cause chain, see `AppError::with_cause`
+  pub fn cause(mut self, cause: &AppError) -> Self {
+    self.causes.extend(cause.cause_chain());
+    self
+  }
+
+  /// Adds the error of a field, translated by `AppError::to_proto`
+  pub fn field_error(
+    mut self,
+    field: impl Into<String>,
+    id: impl Into<String>,
+    params: OptionalParams,
+  ) -> Self {
+    let err = AppErrorError { id: id.into(), params };
+    self.errors.get_or_insert_default().insert(field.into(), err);
+    self
+  }
+
+  /// Adds the error of a field of a nested object or list item, e.g. `("variants.0", "sku")`
+  pub fn nested_field_error(
+    mut self,
+    parent: impl Into<String>,
+    field: impl Into<String>,
+    id: impl Into<String>,
+    params: OptionalParams,
+  ) -> Self {
+    let err = AppErrorError { id: id.into(), params };
+    let nested = self.errors_nested.get_or_insert_default();
+    nested.entry(parent.into()).or_default().insert(field.into(), err);
+    self
+  }
+
+  /// Keeps the message empty and tells the caller not to translate it
+  pub fn skip_translation(mut self) -> Self {
+    self.skip_translation = true;
+    self
+  }
+
+  fn untranslated(self) -> AppError {
+    let errors = AppErrorErrors {
+      err: self.source,
+      errors_internal: self.errors,
+      errors_nested_internal: self.errors_nested,
+    };
+    let mut err = AppError::untranslated(
+      self.ctx,
+      self.path,
+      self.id,
+      self.params,
+      self.details,
+      self.code.into(),
+      Some(errors),
+    );
+    err.skip_translation = self.skip_translation;
+    err.causes = self.causes;
+    err
+  }
+
+  /// Builds the error, translating the message with the default translator
+  pub fn build(self) -> AppError {
+    let mut err = self.untranslated();
+    if !err.skip_translation {
+      let params = err.tr_params.as_ref().filter(|p| !p.is_empty());
+      err.message = tr_ctx(&err.ctx, &err.id, params).unwrap_or_else(|_| err.id.clone());
+    }
+    err
+  }
+
+  /// Same as `build`, but translates with `translator`
+  pub fn build_with(self, translator: &Translator) -> AppError {
+    let mut err = self.untranslated();
+    err.translate_with(translator);
+    err
+  }
+}
+
+impl From<AppError> for Status {
+  fn from(err: AppError) -> Self {
+    err.to_status()
+  }
+}
+
+impl From<&AppError> for Status {
+  fn from(err: &AppError) -> Self {
+    err.to_status()
+  }
+}
+
 /// Convert from proto-generated struct
+///
+/// The params, field error ids and causes are restored from the `AppErrorMetadata` at the
+/// end of `detailed_error`, so the error can be translated again (e.g. for another
+/// language) and chained with `with_cause`.
 pub fn app_error_from_proto_app_error(ctx: Arc<Context>, ae: &AppErrorProto) -> AppError {
   let (errors, nested) = convert_proto_params(ae);
+  let (details, metadata) = AppErrorMetadata::split_from(&ae.detailed_error);
 
-  AppError {
+  let err = AppError {
     ctx,
     id: ae.id.clone(),
     path: ae.r#where.clone(),
     message: ae.message.clone(),
-    detailes: ae.detailed_error.clone(),
+    detailes: details.to_string(),
     request_id: Some(ae.request_id.clone()).filter(|s| !s.is_empty()),
     status_code: ae.status_code as i32,
     tr_params: None,
@@ -323,6 +912,12 @@ pub fn app_error_from_proto_app_error(ctx: Arc<Context>, ae: &AppErrorProto) ->
     errors_nested: nested,
     errors_internal: None,
     errors_nested_internal: None,
+    causes: vec![],
+    logged: AtomicBool::new(false),
+  };
+  match metadata {
+    Some(metadata) => err.with_metadata(metadata),
+    None => err,
   }
 }
 
@@ -345,6 +940,132 @@ pub fn convert_proto_params(
   (Some(shallow), Some(nested))
 }
 
+/// Implemented for responses carrying an `AppErrorProto`. The responses of
+/// `megacommerce_proto` implement it already, `#[derive(SanitizeAppError)]` implements it
+/// for others.
+pub trait SanitizeAppError {
+  /// Replaces the errors of the response with `sanitize_app_error`
+  fn sanitize_app_error_fields(&mut self);
+}
+
+#[cfg(any(feature = "macros", feature = "all"))]
+pub use megacommerce_shared_sanitize_derive::SanitizeAppError;
+
+impl SanitizeAppError for AppErrorProto {
+  fn sanitize_app_error_fields(&mut self) {
+    *self = sanitize_app_error(self);
+  }
+}
+
+impl<T: SanitizeAppError> SanitizeAppError for Option<T> {
+  fn sanitize_app_error_fields(&mut self) {
+    if let Some(inner) = self {
+      inner.sanitize_app_error_fields();
+    }
+  }
+}
+
+impl<T: SanitizeAppError> SanitizeAppError for Vec<T> {
+  fn sanitize_app_error_fields(&mut self) {
+    self.iter_mut().for_each(T::sanitize_app_error_fields);
+  }
+}
+
+impl<T: SanitizeAppError + ?Sized> SanitizeAppError for Box<T> {
+  fn sanitize_app_error_fields(&mut self) {
+    (**self).sanitize_app_error_fields();
+  }
+}
+
+/// What `sanitize_app_error` keeps
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SanitizeConfig {
+  /// Keep `detailed_error`, `where` and the other internal details, for development
+  pub keep_details: bool,
+}
+
+impl SanitizeConfig {
+  /// Keeps the details when `APP_ENV` is `dev`, `development` or `local`
+  pub fn from_env() -> Self {
+    let env = std::env::var("APP_ENV").unwrap_or_default().to_ascii_lowercase();
+    Self { keep_details: matches!(env.as_str(), "dev" | "development" | "local") }
+  }
+}
+
+static SANITIZE_CONFIG: LazyLock<RwLock<SanitizeConfig>> =
+  LazyLock::new(|| RwLock::new(SanitizeConfig::from_env()));
+
+/// Overrides the config read from the environment by `sanitize_app_error`
+pub fn set_sanitize_config(config: SanitizeConfig) {
+  *SANITIZE_CONFIG.write().unwrap() = config;
+}
+
+/// Strips what non-internal callers must not see from an error: `detailed_error` (which
+/// holds the raw database messages) and the internal `where` path. Server errors get the
+/// generic internal error message. The `AppErrorMetadata` is stripped too, also when the
+/// details are kept.
+///
+/// Service to service responses shouldn't be sanitized, so the caller can rehydrate the
+/// error. See `SanitizeConfig` for development.
+pub fn sanitize_app_error(err: &AppErrorProto) -> AppErrorProto {
+  sanitize_app_error_with(err, &SANITIZE_CONFIG.read().unwrap())
+}
+
+/// Same as `sanitize_app_error`, with an explicit config
+pub fn sanitize_app_error_with(err: &AppErrorProto, config: &SanitizeConfig) -> AppErrorProto {
+  let mut err = err.clone();
+  if config.keep_details {
+    err.detailed_error = AppErrorMetadata::split_from(&err.detailed_error).0.to_string();
+    return err;
+  }
+
+  err.detailed_error.clear();
+  err.r#where.clear();
+  // the message of a server error may be an untranslated database or library error
+  if matches!(Code::from(err.status_code), Code::Internal | Code::Unknown | Code::DataLoss)
+    && err.id != MSG_ID_ERR_INTERNAL
+  {
+    err.id = MSG_ID_ERR_INTERNAL.to_string();
+    err.message = MSG_ERR_INTERNAL.to_string();
+    err.skip_translation = false;
+  }
+  err
+}
+
+// The proto responses are foreign types to the services, so they can't implement
+// `SanitizeAppError` for them with `sanitize_app_error!`; it's done once here instead.
+// The names are those of megacommerce-proto 0.4, which Cargo.toml is pinned to: bump both
+// together when a release renames or adds a response.
+macro_rules! impl_sanitize_app_error {
+  ($($ty:ident => $oneof:ident),* $(,)?) => {
+    $(
+      impl SanitizeAppError for megacommerce_proto::$ty {
+        fn sanitize_app_error_fields(&mut self) {
+          if let Some(megacommerce_proto::$oneof::Response::Error(err)) = self.response.as_mut() {
+            *err = sanitize_app_error(err);
+          }
+        }
+      }
+    )*
+  };
+}
+
+impl_sanitize_app_error!(
+  BestSellingProductsResponse => best_selling_products_response,
+  ProductCreateResponse => product_create_response,
+  ProductDataResponse => product_data_response,
+  ProductListResponse => product_list_response,
+  ProductSnapshotResponse => product_snapshot_response,
+  ConfigGetResponse => config_get_response,
+  ConfigUpdateResponse => config_update_response,
+  ConfigListenerResponse => config_listener_response,
+  TranslationsForLangGetResponse => translations_for_lang_get_response,
+  EmailConfirmationResponse => email_confirmation_response,
+  PasswordForgotResponse => password_forgot_response,
+  LoginResponse => login_response,
+  SupplierCreateResponse => supplier_create_response,
+);
+
 // Implement std::fmt::Display for error formatting
 impl fmt::Display for AppError {
   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -357,3 +1078,352 @@ impl Error for AppError {
     self.error.as_ref().map(|e| e.as_ref() as &(dyn Error + 'static))
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use megacommerce_proto::{TranslationElement, TranslationElements};
+
+  fn translator() -> Translator {
+    let trans = HashMap::from([(
+      "en".to_string(),
+      TranslationElements {
+        trans: vec![
+          TranslationElement {
+            id: "order.not_found".into(),
+            tr: "Order {{ id }} is not found".into(),
+          },
+          TranslationElement { id: "field.required".into(), tr: "This field is required".into() },
+        ],
+      },
+    )]);
+    Translator::new(trans, "en".to_string(), vec!["en".to_string()])
+  }
+
+  fn ctx() -> Arc<Context> {
+    Arc::new(Context { accept_language: "en".to_string(), ..Default::default() })
+  }
+
+  #[test]
+  fn test_new_with_translator() {
+    let t = translator();
+    let params = HashMap::from([("id".to_string(), Value::from(7))]);
+    let errors = AppErrorErrors {
+      errors_internal: Some(HashMap::from([(
+        "name".to_string(),
+        AppErrorError { id: "field.required".to_string(), params: None },
+      )])),
+      ..Default::default()
+    };
+    let err = AppError::new_with_translator(
+      &t,
+      ctx(),
+      "orders.get",
+      "order.not_found",
+      Some(params),
+      "",
+      Code::NotFound as i32,
+      Some(errors),
+    );
+    assert_eq!(err.message, "Order 7 is not found");
+
+    let proto = err.to_proto_with(&t);
+    assert_eq!(proto.errors.unwrap().values["name"], "This field is required");
+  }
+
+  #[test]
+  fn test_builder() {
+    let t = translator();
+    let err = AppError::builder(ctx(), "orders.get", "order.not_found")
+      .param("id", 7)
+      .details("select failed")
+      .code(Code::NotFound)
+      .source(std::io::Error::other("connection reset"))
+      .field_error("name", "field.required", None)
+      .nested_field_error("variants.0", "sku", "field.required", None)
+      .build_with(&t);
+
+    assert_eq!(err.message, "Order 7 is not found");
+    assert_eq!(err.status_code, Code::NotFound as i32);
+    assert_eq!(err.detailes, "select failed");
+    assert_eq!(err.unwrap().unwrap().to_string(), "connection reset");
+    assert_eq!(err.errors_internal.as_ref().unwrap()["name"].id, "field.required");
+    let nested = err.errors_nested_internal.as_ref().unwrap();
+    assert_eq!(nested["variants.0"]["sku"].id, "field.required");
+
+    let proto = err.to_proto_with(&t);
+    assert_eq!(proto.errors.unwrap().values["name"], "This field is required");
+
+    let err = AppError::builder(ctx(), "p", "order.not_found").skip_translation().build_with(&t);
+    assert!(err.skip_translation && err.message.is_empty());
+    assert_eq!(err.status_code, Code::Internal as i32);
+  }
+
+  #[test]
+  fn test_status_round_trip() {
+    let t = translator();
+    let ctx = Arc::new(Context { request_id: "req-1".to_string(), ..(*ctx()).clone() });
+    let err = AppError::builder(ctx.clone(), "orders.get", "order.not_found")
+      .param("id", 7)
+      .code(Code::NotFound)
+      .field_error("name", "field.required", None)
+      .build_with(&t);
+
+    let status = err.to_internal_status_with(&t);
+    assert_eq!(status.code(), Code::NotFound);
+    assert_eq!(status.message(), "Order 7 is not found");
+
+    let back = AppError::from_status(ctx.clone(), &status);
+    assert_eq!(back.id, "order.not_found");
+    assert_eq!(back.path, "orders.get");
+    assert_eq!(back.status_code, Code::NotFound as i32);
+    assert_eq!(back.request_id.as_deref(), Some("req-1"));
+    assert_eq!(back.errors.unwrap()["name"], "This field is required");
+    assert_eq!(back.errors_internal.unwrap()["name"].id, "field.required");
+    assert_eq!(back.tr_params.unwrap()["id"], 7);
+
+    // sanitized for clients
+    set_sanitize_config(SanitizeConfig::default());
+    let back = AppError::from_status(ctx.clone(), &err.to_status_with(&t));
+    assert_eq!(back.id, "order.not_found");
+    assert_eq!(back.message, "Order 7 is not found");
+    assert!(back.path.is_empty() && back.tr_params.is_none());
+
+    let back = AppError::from_status(ctx, &Status::unavailable("connection refused"));
+    assert_eq!(back.id, MSG_ID_ERR_INTERNAL);
+    assert_eq!(back.status_code, Code::Unavailable as i32);
+    assert_eq!(back.message, "connection refused");
+  }
+
+  #[test]
+  fn test_proto_round_trip() {
+    let t = translator();
+    let inventory = AppError::builder(ctx(), "inventory.reserve", "inventory.out_of_stock")
+      .code(Code::FailedPrecondition)
+      .build_with(&t);
+    let err = AppError::builder(ctx(), "orders.get", "order.not_found")
+      .param("id", 7)
+      .details("no rows")
+      .code(Code::NotFound)
+      .field_error("name", "field.required", None)
+      .nested_field_error("variants.0", "sku", "field.required", None)
+      .cause(&inventory)
+      .build_with(&t)
+      .with_cause(&AppError::builder(ctx(), "db.select", "db.no_rows").build_with(&t));
+
+    let proto = err.to_proto_with(&t);
+    assert_eq!(
+      proto.errors_nested.as_ref().unwrap().data.keys().collect::<Vec<_>>(),
+      ["variants.0"]
+    );
+    // through the wire
+    let proto = AppErrorProto::decode(proto.encode_to_vec().as_slice()).unwrap();
+
+    let back = app_error_from_proto_app_error(ctx(), &proto);
+    assert_eq!(back.id, err.id);
+    assert_eq!(back.path, err.path);
+    assert_eq!(back.message, err.message);
+    assert_eq!(back.detailes, err.detailes);
+    assert_eq!(back.status_code, err.status_code);
+    assert_eq!(back.skip_translation, err.skip_translation);
+    assert_eq!(back.tr_params, err.tr_params);
+    assert_eq!(back.errors_internal, err.errors_internal);
+    assert_eq!(back.errors_nested_internal, err.errors_nested_internal);
+    let causes: Vec<_> = back.causes.iter().map(|c| (c.path.as_str(), c.id.as_str())).collect();
+    assert_eq!(
+      causes,
+      vec![("inventory.reserve", "inventory.out_of_stock"), ("db.select", "db.no_rows")]
+    );
+    assert_eq!(back.to_proto_with(&t), proto);
+
+    // translated again from the ids and params
+    let mut trans = HashMap::new();
+    trans.insert(
+      "ar".to_string(),
+      TranslationElements {
+        trans: vec![TranslationElement {
+          id: "order.not_found".into(),
+          tr: "الطلب {{ id }} غير موجود".into(),
+        }],
+      },
+    );
+    let ar = Translator::new(trans, "ar".to_string(), vec!["ar".to_string()]);
+    let mut back = app_error_from_proto_app_error(ctx(), &proto);
+    back.translate_with(&ar);
+    assert_eq!(back.message, "الطلب 7 غير موجود");
+  }
+
+  #[test]
+  fn test_to_proto_nested_errors() {
+    let t = translator();
+    let mut err = AppError::builder(ctx(), "products.create", "order.not_found")
+      .param("id", 1)
+      .nested_field_error("variants.0", "sku", "field.required", None)
+      .nested_field_error(
+        "variants.1",
+        "price",
+        "order.not_found",
+        Some(HashMap::from([("id".to_string(), Value::from(9))])),
+      )
+      .build_with(&t);
+    // already translated, e.g. received from another service
+    err.errors_nested = Some(HashMap::from([
+      ("variants.0".to_string(), HashMap::from([("name".to_string(), "Too long".to_string())])),
+      ("variants.1".to_string(), HashMap::from([("price".to_string(), "stale".to_string())])),
+    ]));
+
+    let proto = err.to_proto_with(&t);
+    let nested = proto.errors_nested.unwrap().data;
+    assert_eq!(nested["variants.0"].values["sku"], "This field is required");
+    assert_eq!(nested["variants.0"].values["name"], "Too long");
+    assert_eq!(nested["variants.1"].values["price"], "Order 9 is not found");
+    assert_eq!(nested["variants.1"].values.len(), 1);
+  }
+
+  #[test]
+  fn test_sanitize_app_error() {
+    let t = translator();
+    let err = AppError::builder(ctx(), "orders.get", "order.not_found")
+      .param("id", 7)
+      .details("Key (id)=(7) is not present in table orders")
+      .code(Code::NotFound)
+      .nested_field_error("variants.0", "sku", "field.required", None)
+      .build_with(&t);
+    let proto = err.to_proto_with(&t);
+
+    let clean = sanitize_app_error_with(&proto, &SanitizeConfig::default());
+    assert!(clean.detailed_error.is_empty() && clean.r#where.is_empty());
+    assert_eq!(clean.message, "Order 7 is not found");
+    let nested = clean.errors_nested.unwrap().data;
+    assert_eq!(nested["variants.0"].values["sku"], "This field is required");
+
+    let dev = SanitizeConfig { keep_details: true };
+    let kept = sanitize_app_error_with(&proto, &dev);
+    assert_eq!(kept.detailed_error, "Key (id)=(7) is not present in table orders");
+    assert_eq!(kept.r#where, proto.r#where);
+    // without the metadata, nothing is restored
+    let back = app_error_from_proto_app_error(ctx(), &kept);
+    assert!(back.tr_params.is_none() && back.errors_nested_internal.is_none());
+
+    let db = AppErrorProto {
+      id: "duplicate key value violates unique constraint \"users_email_key\"".to_string(),
+      message: "duplicate key value violates unique constraint \"users_email_key\"".to_string(),
+      status_code: Code::Internal as i32,
+      skip_translation: true,
+      ..Default::default()
+    };
+    let clean = sanitize_app_error_with(&db, &SanitizeConfig::default());
+    assert_eq!(
+      (clean.id.as_str(), clean.message.as_str()),
+      (MSG_ID_ERR_INTERNAL, MSG_ERR_INTERNAL)
+    );
+  }
+
+  #[test]
+  fn test_status_sanitized() {
+    set_sanitize_config(SanitizeConfig::default());
+    let err = AppError::builder(ctx(), "products.create", "duplicate key value")
+      .details("Key (sku)=(A-1) already exists.")
+      .skip_translation()
+      .build_with(&translator());
+    let status = Status::from(err);
+    assert_eq!(status.message(), MSG_ERR_INTERNAL);
+    let proto = AppErrorProto::decode(status.details()).unwrap();
+    assert!(proto.detailed_error.is_empty() && proto.r#where.is_empty());
+    assert_eq!(
+      (proto.id.as_str(), proto.message.as_str()),
+      (MSG_ID_ERR_INTERNAL, MSG_ERR_INTERNAL)
+    );
+  }
+
+  #[test]
+  fn test_new_with_translator_unknown_id() {
+    let err =
+      AppError::new_with_translator(&translator(), ctx(), "p", "unknown.id", None, "", 13, None);
+    assert_eq!(err.message, "unknown.id");
+  }
+
+  #[test]
+  fn test_error_type_status() {
+    assert_eq!(ErrorType::NoRows.grpc_code(), Code::NotFound);
+    assert_eq!(ErrorType::NoRows.http_status(), StatusCode::NOT_FOUND);
+    assert_eq!(ErrorType::UniqueViolation.grpc_code(), Code::AlreadyExists);
+    assert_eq!(ErrorType::UniqueViolation.http_status(), StatusCode::CONFLICT);
+    assert_eq!(ErrorType::NotNullViolation.http_status(), StatusCode::BAD_REQUEST);
+    assert_eq!(ErrorType::Connection.http_status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(ErrorType::HttpEmptyResponse.grpc_code(), Code::Internal);
+    assert_eq!(ErrorType::HttpEmptyResponse.http_status(), StatusCode::BAD_GATEWAY);
+    assert_eq!(ErrorType::Internal.http_status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+    assert!(ErrorType::Connection.is_retryable() && ErrorType::TimedOut.is_retryable());
+    assert!(!ErrorType::UniqueViolation.is_retryable() && !ErrorType::Internal.is_retryable());
+  }
+
+  // collects the fields of the events
+  #[derive(Clone, Default)]
+  struct Events(Arc<std::sync::Mutex<Vec<HashMap<String, String>>>>);
+
+  impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for Events {
+    fn on_event(&self, event: &tracing::Event<'_>, _: tracing_subscriber::layer::Context<'_, S>) {
+      struct Visitor(HashMap<String, String>);
+      impl tracing::field::Visit for Visitor {
+        fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
+          self.0.insert(field.name().to_string(), value.to_string());
+        }
+        fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn fmt::Debug) {
+          self.0.insert(field.name().to_string(), format!("{value:?}"));
+        }
+      }
+      let mut visitor = Visitor(HashMap::new());
+      event.record(&mut visitor);
+      self.0.lock().unwrap().push(visitor.0);
+    }
+  }
+
+  #[test]
+  fn test_log() {
+    use tracing_subscriber::layer::SubscriberExt;
+
+    let mut request = Context { request_id: "req-1".into(), ..Default::default() };
+    request.session.user_id = "user-1".into();
+    request.session.token = "secret-token".into();
+    let request_ctx = Arc::new(request);
+    assert!(!format!("{request_ctx} {request_ctx:?}").contains("secret-token"));
+
+    let source = InternalError::new(
+      "db.connect".into(),
+      std::io::Error::other("connection reset").into(),
+      ErrorType::Connection,
+      true,
+      "can't connect".into(),
+    );
+    let err = AppError::builder(request_ctx, "orders.get", MSG_ID_ERR_INTERNAL)
+      .source(source)
+      .cause(
+        &AppError::builder(ctx(), "inventory.get", "inventory.error").build_with(&translator()),
+      )
+      .build_with(&translator());
+
+    let err = AppError { detailes: "Key (email)=(sam@example.com) already exists".into(), ..err };
+    let events = Events::default();
+    let subscriber = tracing_subscriber::registry().with(events.clone());
+    tracing::subscriber::with_default(subscriber, || {
+      // the first conversion logs, the next ones don't
+      err.to_proto_with(&translator());
+      err.to_status_with(&translator());
+      err.to_proto_with(&translator());
+    });
+
+    let events = events.0.lock().unwrap();
+    assert_eq!(events.len(), 1);
+    let event = &events[0];
+    assert_eq!(event["request_id"], "req-1");
+    assert_eq!(event["user_id"], "user-1");
+    assert_eq!(event["err_type"], "connection_exception");
+    assert_eq!(event["path"], "orders.get");
+    assert_eq!(event["status_code"], "13");
+    assert!(event["cause_chain"].contains("connection reset"));
+    assert_eq!(event["causes"], "inventory.get inventory.error");
+    assert!(event.values().all(|v| !v.contains("secret-token") && !v.contains("sam@")));
+  }
+}
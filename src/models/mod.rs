@@ -1,4 +1,5 @@
 pub mod context;
+pub mod error_sink;
 pub mod errors;
 pub mod files;
 pub mod images;
@@ -0,0 +1,147 @@
+use std::collections::HashMap;
+
+use megacommerce_shared_translate_core::tera_error_string;
+use serde_json::Value;
+
+use super::format::register_filters;
+use super::message::{Message, select_leaves};
+use super::{TranslationError, TranslationKey, TranslationsDiff};
+
+/// A parsed translation of one key
+#[derive(Debug)]
+pub(crate) struct CompiledMessage {
+  pub(crate) source: String,
+  pub(crate) message: Message,
+  pub(crate) has_vars: bool,
+  /// Names of the compiled Tera templates, one per leaf of `message`
+  names: Vec<String>,
+  /// Set when a Tera template of the message doesn't compile
+  pub(crate) error: Option<String>,
+  /// Set when a plural/select block doesn't parse and the message is rendered as plain text
+  pub(crate) syntax_error: Option<String>,
+}
+
+/// All the translations of one language, compiled once into a single immutable
+/// `tera::Tera`. Rendering only needs `&self`, so any number of threads can render
+/// the same key concurrently without locks.
+#[derive(Debug)]
+pub(crate) struct LanguageCatalog {
+  tera: tera::Tera,
+  pub(crate) messages: HashMap<String, CompiledMessage>,
+}
+
+pub(crate) type Catalog = HashMap<String, LanguageCatalog>;
+
+impl LanguageCatalog {
+  pub(crate) fn new(lang: &str, trans: HashMap<String, String>) -> Self {
+    let mut tera = tera::Tera::default();
+    register_filters(&mut tera, lang);
+    let mut messages = HashMap::with_capacity(trans.len());
+    let mut templates = vec![];
+
+    for (id, source) in trans {
+      // an invalid plural/select block is rendered as plain text
+      let (message, syntax_error) = match Message::parse(&source) {
+        Ok(message) => (message, None),
+        Err(e) => (Message::plain(&source), Some(e)),
+      };
+      let has_vars = message.has_blocks() || (source.contains("{{") && source.contains("}}"));
+
+      let names: Vec<_> = (0..message.leaves.len()).map(|i| format!("{id}#{i}")).collect();
+      // parse each template on its own so one broken key doesn't fail the whole language
+      let error = match names
+        .iter()
+        .zip(&message.leaves)
+        .try_for_each(|(name, leaf)| tera::Template::new(name, None, leaf).map(drop))
+      {
+        Ok(()) => {
+          templates.extend(names.iter().cloned().zip(message.leaves.iter().cloned()));
+          None
+        }
+        Err(e) => Some(tera_error_string(&e)),
+      };
+
+      messages
+        .insert(id, CompiledMessage { source, message, has_vars, names, error, syntax_error });
+    }
+
+    if let Err(e) = tera.add_raw_templates(templates) {
+      // only reachable with inheritance between templates, which translations don't use
+      let error = tera_error_string(&e);
+      messages.values_mut().for_each(|m| m.error = Some(error.clone()));
+    }
+
+    Self { tera, messages }
+  }
+
+  pub(crate) fn get(&self, id: &str) -> Option<&CompiledMessage> {
+    self.messages.get(id)
+  }
+
+  pub(crate) fn render(
+    &self,
+    lang: &str,
+    id: &str,
+    params: Option<Value>,
+  ) -> Result<String, TranslationError> {
+    let msg = self.get(id).ok_or_else(|| TranslationError::KeyNotFound(id.to_string()))?;
+    let Some(params) = params else {
+      if msg.has_vars {
+        return Err(TranslationError::MissingParams);
+      }
+      // For non-parameterized templates, just return the raw template string
+      return Ok(msg.source.clone());
+    };
+    if let Some(e) = &msg.error {
+      return Err(TranslationError::RenderError(e.clone()));
+    }
+
+    let leaves = select_leaves(&msg.message, lang, &params)?;
+    let context = tera::Context::from_value(params)?;
+    if let [leaf] = leaves[..] {
+      return Ok(self.tera.render(&msg.names[leaf], &context)?);
+    }
+    leaves.into_iter().try_fold(String::new(), |mut out, leaf| {
+      out.push_str(&self.tera.render(&msg.names[leaf], &context)?);
+      Ok(out)
+    })
+  }
+}
+
+/// Includes the causes, tera puts the actual syntax error in the source
+pub(crate) fn build_catalog(parsed: HashMap<String, HashMap<String, String>>) -> Catalog {
+  parsed
+    .into_iter()
+    .map(|(lang, trans)| {
+      let catalog = LanguageCatalog::new(&lang, trans);
+      (lang, catalog)
+    })
+    .collect()
+}
+
+pub(crate) fn diff_catalogs(previous: &Catalog, current: &Catalog) -> TranslationsDiff {
+  let mut diff = TranslationsDiff::default();
+  let key = |lang: &str, id: &str| TranslationKey { lang: lang.to_string(), id: id.to_string() };
+
+  for (lang, catalog) in current {
+    for (id, msg) in &catalog.messages {
+      match previous.get(lang).and_then(|c| c.get(id)) {
+        Some(old) if old.source == msg.source => {}
+        Some(_) => diff.changed.push(key(lang, id)),
+        None => diff.added.push(key(lang, id)),
+      }
+    }
+  }
+  for (lang, catalog) in previous {
+    for id in catalog.messages.keys() {
+      if current.get(lang).and_then(|c| c.get(id)).is_none() {
+        diff.removed.push(key(lang, id));
+      }
+    }
+  }
+
+  diff.added.sort();
+  diff.removed.sort();
+  diff.changed.sort();
+  diff
+}
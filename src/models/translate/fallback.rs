@@ -0,0 +1,156 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use arc_swap::ArcSwap;
+
+use super::TranslationKey;
+use super::negotiate::parent_locale;
+
+/// Called with `(lang, id)` every time a key is missing in the requested language
+pub type MissingKeyHook = Arc<dyn Fn(&str, &str) + Send + Sync>;
+
+/// Where `Translator::tr` looks for a key that is missing in the requested language.
+///
+/// The default chain is: requested → parent locales → default language, and
+/// `KeyNotFound` if none of them has the key.
+#[derive(Debug, Clone)]
+pub struct FallbackChain {
+  /// Try the parent locales of the requested language (`ar-EG` → `ar`)
+  pub parent_locales: bool,
+  /// Extra languages tried in order after the parent locales
+  pub languages: Vec<String>,
+  /// Try the default language of the translator
+  pub default_language: bool,
+  /// Return the id itself instead of `TranslationError::KeyNotFound`
+  pub id: bool,
+}
+
+impl Default for FallbackChain {
+  fn default() -> Self {
+    Self { parent_locales: true, languages: vec![], default_language: true, id: false }
+  }
+}
+
+impl FallbackChain {
+  /// A chain that only looks at the requested language
+  pub fn none() -> Self {
+    Self { parent_locales: false, languages: vec![], default_language: false, id: false }
+  }
+
+  /// The languages to try after `lang`, in order and without duplicates
+  pub fn languages_for(&self, lang: &str, default_language: &str) -> Vec<String> {
+    let mut out: Vec<String> = vec![];
+    let mut push = |l: &str| {
+      if l != lang && !out.iter().any(|o| o == l) {
+        out.push(l.to_string());
+      }
+    };
+
+    if self.parent_locales {
+      let mut tag = lang;
+      while let Some(parent) = parent_locale(tag) {
+        push(parent);
+        tag = parent;
+      }
+    }
+    self.languages.iter().for_each(|l| push(l));
+    if self.default_language {
+      push(default_language);
+    }
+
+    out
+  }
+}
+
+type Counters = HashMap<String, HashMap<String, Arc<AtomicU64>>>;
+
+/// Counts the keys missing per language, so translators can fill the gaps.
+///
+/// A key seen before is counted without locking, only the first miss of a key copies
+/// the map of counters.
+#[derive(Debug, Default)]
+pub struct MissingKeys(ArcSwap<Counters>);
+
+impl MissingKeys {
+  pub fn record(&self, lang: &str, id: &str) {
+    if let Some(counter) = self.0.load().get(lang).and_then(|ids| ids.get(id)) {
+      counter.fetch_add(1, Ordering::Relaxed);
+      return;
+    }
+
+    // `rcu` retries if another key was added in the meantime
+    let mut counter = None;
+    self.0.rcu(|counters| {
+      let mut counters = Counters::clone(counters);
+      let ids = counters.entry(lang.to_string()).or_default();
+      counter = Some(ids.entry(id.to_string()).or_default().clone());
+      counters
+    });
+    if let Some(counter) = counter {
+      counter.fetch_add(1, Ordering::Relaxed);
+    }
+  }
+
+  /// Returns how many times each key was missing
+  pub fn snapshot(&self) -> HashMap<TranslationKey, u64> {
+    self.collect(|c| c.load(Ordering::Relaxed))
+  }
+
+  /// Returns the counters and resets them. The keys are kept, so a miss recorded
+  /// concurrently is counted in this or the next call.
+  pub fn take(&self) -> HashMap<TranslationKey, u64> {
+    self.collect(|c| c.swap(0, Ordering::Relaxed))
+  }
+
+  fn collect(&self, count: impl Fn(&AtomicU64) -> u64) -> HashMap<TranslationKey, u64> {
+    let counters = self.0.load();
+    let mut out = HashMap::new();
+    for (lang, ids) in counters.iter() {
+      for (id, counter) in ids {
+        let n = count(counter);
+        if n > 0 {
+          out.insert(TranslationKey { lang: lang.clone(), id: id.clone() }, n);
+        }
+      }
+    }
+    out
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_languages_for() {
+    let chain = FallbackChain { languages: vec!["fr".into(), "en".into()], ..Default::default() };
+    assert_eq!(chain.languages_for("ar-Arab-EG", "en"), vec!["ar-Arab", "ar", "fr", "en"]);
+    assert_eq!(chain.languages_for("en", "en"), vec!["fr"]);
+    assert!(FallbackChain::none().languages_for("ar-EG", "en").is_empty());
+  }
+
+  #[test]
+  fn test_missing_keys() {
+    let missing = Arc::new(MissingKeys::default());
+    let handles: Vec<_> = (0..8)
+      .map(|i| {
+        let missing = missing.clone();
+        std::thread::spawn(move || {
+          for _ in 0..100 {
+            missing.record("ar", "shared");
+            missing.record("ar", &format!("k{}", i % 2));
+          }
+        })
+      })
+      .collect();
+    handles.into_iter().for_each(|h| h.join().unwrap());
+
+    let key = |id: &str| TranslationKey { lang: "ar".into(), id: id.into() };
+    let counts = missing.take();
+    assert_eq!(counts, HashMap::from([(key("shared"), 800), (key("k0"), 400), (key("k1"), 400)]));
+    assert!(missing.snapshot().is_empty());
+    missing.record("ar", "k0");
+    assert_eq!(missing.snapshot(), HashMap::from([(key("k0"), 1)]));
+  }
+}
@@ -0,0 +1,189 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use megacommerce_proto::{TranslationElement, TranslationElements};
+use megacommerce_shared_translate_core::flatten;
+use serde_json::Value;
+
+use super::TranslationError;
+
+/// The format of per-language translation files, `<lang>.json` or `<lang>.yaml`/`<lang>.yml`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TranslationsFormat {
+  Json,
+  Yaml,
+}
+
+impl TranslationsFormat {
+  pub fn from_path(path: &Path) -> Option<Self> {
+    match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+      "json" => Some(Self::Json),
+      "yaml" | "yml" => Some(Self::Yaml),
+      _ => None,
+    }
+  }
+
+  pub fn extension(&self) -> &'static str {
+    match self {
+      Self::Json => "json",
+      Self::Yaml => "yaml",
+    }
+  }
+}
+
+fn file_error(path: &Path, err: impl ToString) -> TranslationError {
+  TranslationError::File { path: path.to_path_buf(), err: err.to_string() }
+}
+
+/// Reads a directory of per-language files into the shape returned by the translation
+/// service, so it can be passed to `translations_init`, `Translator::new` or `reload`.
+///
+/// Each `<lang>.json`, `<lang>.yaml` or `<lang>.yml` file holds a map of ids to
+/// translations. Nested maps are flattened with dots:
+///
+/// ```yaml
+/// order:
+///   not_found: "Order {{ id }} is not found"   # order.not_found
+/// ```
+///
+/// Files with other extensions are ignored.
+pub fn load_translations_dir(
+  dir: impl AsRef<Path>,
+) -> Result<HashMap<String, TranslationElements>, TranslationError> {
+  let dir = dir.as_ref();
+  let mut result = HashMap::new();
+
+  for entry in fs::read_dir(dir).map_err(|e| file_error(dir, e))? {
+    let path = entry.map_err(|e| file_error(dir, e))?.path();
+    let (Some(format), Some(lang)) =
+      (TranslationsFormat::from_path(&path), path.file_stem().and_then(|s| s.to_str()))
+    else {
+      continue;
+    };
+    if !path.is_file() {
+      continue;
+    }
+    if result.contains_key(lang) {
+      return Err(file_error(&path, format!("duplicate translations for language {lang}")));
+    }
+
+    let trans = load_translations_file(&path, format)?;
+    result.insert(lang.to_string(), trans);
+  }
+
+  Ok(result)
+}
+
+/// Reads the translations of a single language, see `load_translations_dir`
+pub fn load_translations_file(
+  path: impl AsRef<Path>,
+  format: TranslationsFormat,
+) -> Result<TranslationElements, TranslationError> {
+  let path = path.as_ref();
+  let content = fs::read_to_string(path).map_err(|e| file_error(path, e))?;
+  let value: Value = match format {
+    TranslationsFormat::Json => serde_json::from_str(&content).map_err(|e| file_error(path, e))?,
+    TranslationsFormat::Yaml => serde_yaml::from_str(&content).map_err(|e| file_error(path, e))?,
+  };
+
+  let mut flat = BTreeMap::new();
+  flatten(&value, String::new(), &mut flat).map_err(|e| file_error(path, e))?;
+
+  Ok(TranslationElements {
+    trans: flat.into_iter().map(|(id, tr)| TranslationElement { id, tr }).collect(),
+  })
+}
+
+/// Writes one `<lang>.<ext>` file per language into `dir`, creating it if needed.
+///
+/// Ids are written flat and sorted, so exporting the same translations twice gives
+/// the same files. Returns the written paths.
+pub fn export_translations_dir(
+  dir: impl AsRef<Path>,
+  trans: &HashMap<String, TranslationElements>,
+  format: TranslationsFormat,
+) -> Result<Vec<PathBuf>, TranslationError> {
+  let dir = dir.as_ref();
+  fs::create_dir_all(dir).map_err(|e| file_error(dir, e))?;
+
+  let mut paths = vec![];
+  for (lang, elements) in trans {
+    let path = dir.join(format!("{lang}.{}", format.extension()));
+    let flat: BTreeMap<_, _> = elements.trans.iter().map(|e| (&e.id, &e.tr)).collect();
+    let content = match format {
+      TranslationsFormat::Json => {
+        serde_json::to_string_pretty(&flat).map(|s| s + "\n").map_err(|e| file_error(&path, e))?
+      }
+      TranslationsFormat::Yaml => serde_yaml::to_string(&flat).map_err(|e| file_error(&path, e))?,
+    };
+
+    fs::write(&path, content).map_err(|e| file_error(&path, e))?;
+    paths.push(path);
+  }
+
+  paths.sort();
+  Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn temp_dir() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("translations-{}", ulid::Ulid::new()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+  }
+
+  fn pairs(elements: &TranslationElements) -> Vec<(&str, &str)> {
+    elements.trans.iter().map(|e| (e.id.as_str(), e.tr.as_str())).collect()
+  }
+
+  #[test]
+  fn test_load_translations_dir() {
+    let dir = temp_dir();
+    fs::write(dir.join("en.yaml"), "order:\n  not_found: \"Order {{ id }} not found\"\nok: OK\n")
+      .unwrap();
+    fs::write(dir.join("ar.json"), r#"{"order.not_found": "الطلب {{ id }} غير موجود"}"#).unwrap();
+    fs::write(dir.join("README.md"), "ignored").unwrap();
+
+    let trans = load_translations_dir(&dir).unwrap();
+    assert_eq!(trans.len(), 2);
+    assert_eq!(
+      pairs(&trans["en"]),
+      vec![("ok", "OK"), ("order.not_found", "Order {{ id }} not found")]
+    );
+    assert_eq!(pairs(&trans["ar"]), vec![("order.not_found", "الطلب {{ id }} غير موجود")]);
+
+    fs::write(dir.join("fr.json"), r#"{"a": ["x"]}"#).unwrap();
+    assert!(matches!(load_translations_dir(&dir), Err(TranslationError::File { .. })));
+    fs::remove_dir_all(dir).unwrap();
+  }
+
+  #[test]
+  fn test_export_translations_dir() {
+    let dir = temp_dir();
+    let elements = |items: &[(&str, &str)]| TranslationElements {
+      trans: items
+        .iter()
+        .map(|(id, tr)| TranslationElement { id: id.to_string(), tr: tr.to_string() })
+        .collect(),
+    };
+    let trans = HashMap::from([
+      ("en".to_string(), elements(&[("b", "B: {{ x }}"), ("a", "A")])),
+      ("ar".to_string(), elements(&[("a", "أ")])),
+    ]);
+
+    for format in [TranslationsFormat::Json, TranslationsFormat::Yaml] {
+      let out = dir.join(format.extension());
+      let paths = export_translations_dir(&out, &trans, format).unwrap();
+      assert_eq!(paths.len(), 2);
+
+      let loaded = load_translations_dir(&out).unwrap();
+      assert_eq!(pairs(&loaded["en"]), vec![("a", "A"), ("b", "B: {{ x }}")]);
+      assert_eq!(pairs(&loaded["ar"]), vec![("a", "أ")]);
+    }
+    fs::remove_dir_all(dir).unwrap();
+  }
+}
@@ -0,0 +1,462 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::{self, Write};
+
+use chrono::{DateTime, FixedOffset, Locale, NaiveDate, NaiveDateTime, Utc};
+use chrono_tz::Tz;
+use serde_json::Value;
+
+/// The digits a number is written with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Digits {
+  /// 0123456789
+  Latin,
+  /// ٠١٢٣٤٥٦٧٨٩
+  ArabicIndic,
+  /// ۰۱۲۳۴۵۶۷۸۹, used in Persian
+  ExtendedArabicIndic,
+}
+
+impl Digits {
+  pub fn parse(s: &str) -> Option<Self> {
+    match s {
+      "latn" => Some(Self::Latin),
+      "arab" => Some(Self::ArabicIndic),
+      "arabext" => Some(Self::ExtendedArabicIndic),
+      _ => None,
+    }
+  }
+
+  /// Replaces the latin digits of `s`
+  pub fn localize(&self, s: &str) -> String {
+    let zero = match self {
+      Self::Latin => return s.to_string(),
+      Self::ArabicIndic => 0x0660,
+      Self::ExtendedArabicIndic => 0x06F0,
+    };
+    s.chars()
+      .map(|c| match c.to_digit(10) {
+        Some(d) if c.is_ascii_digit() => char::from_u32(zero + d).unwrap_or(c),
+        _ => c,
+      })
+      .collect()
+  }
+}
+
+/// Separators and digits of a language, a small subset of the CLDR number symbols
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NumberLocale {
+  pub group: &'static str,
+  pub decimal: &'static str,
+  pub percent: &'static str,
+  pub digits: Digits,
+  /// `$1.00` instead of `1.00 $`
+  pub currency_prefix: bool,
+}
+
+impl NumberLocale {
+  pub fn for_language(lang: &str) -> Self {
+    let lang = lang.replace('_', "-");
+    let (primary, region) = match lang.split_once('-') {
+      Some((p, rest)) => (p, rest.rsplit('-').next().unwrap_or_default()),
+      None => (lang.as_str(), ""),
+    };
+
+    let latin = |group, decimal, percent| Self {
+      group,
+      decimal,
+      percent,
+      digits: Digits::Latin,
+      currency_prefix: false,
+    };
+    match primary.to_ascii_lowercase().as_str() {
+      // the Maghreb uses latin digits
+      "ar" if matches!(region.to_ascii_uppercase().as_str(), "MA" | "DZ" | "TN" | "LY" | "EH") => {
+        latin(".", ",", "%")
+      }
+      "ar" => Self { digits: Digits::ArabicIndic, ..latin("٬", "٫", "٪\u{061C}") },
+      "fa" => Self { digits: Digits::ExtendedArabicIndic, ..latin("٬", "٫", "٪") },
+      "fr" => latin("\u{202F}", ",", "\u{202F}%"),
+      "de" | "es" => latin(".", ",", "\u{A0}%"),
+      "it" | "pt" | "nl" | "tr" | "id" => latin(".", ",", "%"),
+      "ru" | "uk" | "pl" | "cs" | "sv" => latin("\u{A0}", ",", "\u{A0}%"),
+      _ => Self { currency_prefix: true, ..latin(",", ".", "%") },
+    }
+  }
+
+  /// Overrides the digits, and the separators with the ones that go with them, so
+  /// `latn` in Arabic gives `1,235` rather than `1٬235`
+  fn with_digits(mut self, digits: Option<Digits>) -> Self {
+    let Some(digits) = digits.filter(|d| *d != self.digits) else {
+      return self;
+    };
+    (self.group, self.decimal, self.percent) = match digits {
+      Digits::Latin => (",", ".", "%"),
+      Digits::ArabicIndic => ("٬", "٫", "٪\u{061C}"),
+      Digits::ExtendedArabicIndic => ("٬", "٫", "٪"),
+    };
+    self.digits = digits;
+    self
+  }
+
+  /// Joins the integer and fraction digits with the separators of the locale
+  fn decimal_parts(&self, negative: bool, int: &str, frac: &str) -> String {
+    let mut out = String::with_capacity(int.len() + frac.len() + 8);
+    if negative {
+      out.push('-');
+    }
+    for (i, c) in int.chars().enumerate() {
+      if i > 0 && (int.len() - i).is_multiple_of(3) {
+        out.push_str(self.group);
+      }
+      out.push(c);
+    }
+    if !frac.is_empty() {
+      out.push_str(self.decimal);
+      out.push_str(frac);
+    }
+    self.digits.localize(&out)
+  }
+
+  /// Formats `value` with `decimals` fraction digits and grouped thousands
+  pub fn number(&self, value: f64, decimals: usize) -> String {
+    let s = format!("{:.*}", decimals, value.abs());
+    let (int, frac) = s.split_once('.').unwrap_or((&s, ""));
+    let negative = value < 0.0 && s.chars().any(|c| matches!(c, '1'..='9'));
+    self.decimal_parts(negative, int, frac)
+  }
+
+  /// Formats a fraction (`0.25`) as a percent (`25%`)
+  pub fn percent(&self, value: f64, decimals: usize) -> String {
+    format!("{}{}", self.number(value * 100.0, decimals), self.percent)
+  }
+
+  /// Formats an amount given in minor units (cents) of `currency`
+  pub fn currency_minor(&self, amount: i64, currency: &str) -> String {
+    let exp = currency_exponent(currency);
+    let unit = 10u64.pow(exp);
+    let (int, frac) = (amount.unsigned_abs() / unit, amount.unsigned_abs() % unit);
+    let frac =
+      if exp == 0 { String::new() } else { format!("{:0width$}", frac, width = exp as usize) };
+    self.with_symbol(self.decimal_parts(amount < 0, &int.to_string(), &frac), currency)
+  }
+
+  /// Formats an amount given in major units of `currency`, rounded to its minor units
+  pub fn currency(&self, amount: f64, currency: &str) -> String {
+    let number = self.number(amount, currency_exponent(currency) as usize);
+    self.with_symbol(number, currency)
+  }
+
+  fn with_symbol(&self, number: String, currency: &str) -> String {
+    let symbol = currency_symbol(currency);
+    if self.currency_prefix {
+      // the sign goes before the symbol, and a code needs a space but a symbol
+      // doesn't: `-$1.00`, `EGP 1.00`
+      let (sign, number) = number.strip_prefix('-').map_or(("", number.as_str()), |n| ("-", n));
+      if symbol == currency {
+        format!("{sign}{symbol}\u{A0}{number}")
+      } else {
+        format!("{sign}{symbol}{number}")
+      }
+    } else {
+      format!("{number}\u{A0}{symbol}")
+    }
+  }
+}
+
+/// The number of minor unit digits of an ISO 4217 currency
+pub fn currency_exponent(currency: &str) -> u32 {
+  match currency.to_ascii_uppercase().as_str() {
+    "JPY" | "KRW" | "VND" | "CLP" | "ISK" | "UGX" | "XAF" | "XOF" | "PYG" | "RWF" => 0,
+    "KWD" | "BHD" | "OMR" | "JOD" | "IQD" | "LYD" | "TND" => 3,
+    _ => 2,
+  }
+}
+
+fn currency_symbol(currency: &str) -> &str {
+  match currency.to_ascii_uppercase().as_str() {
+    "USD" => "$",
+    "EUR" => "€",
+    "GBP" => "£",
+    "JPY" => "¥",
+    "INR" => "₹",
+    "TRY" => "₺",
+    _ => currency,
+  }
+}
+
+/// A timezone from the `x-timezone` header, an IANA name or a fixed offset
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Timezone {
+  Named(Tz),
+  Offset(FixedOffset),
+}
+
+impl Timezone {
+  /// Parses `Africa/Cairo`, `UTC` or `+02:00`
+  pub fn parse(s: &str) -> Option<Self> {
+    let s = s.trim();
+    if let Ok(tz) = s.parse::<Tz>() {
+      return Some(Self::Named(tz));
+    }
+    DateTime::parse_from_str(&format!("2000-01-01 00:00 {s}"), "%Y-%m-%d %H:%M %:z")
+      .ok()
+      .map(|dt| Self::Offset(*dt.offset()))
+  }
+}
+
+thread_local! {
+  static RENDER_TIMEZONE: RefCell<Option<Timezone>> = const { RefCell::new(None) };
+}
+
+/// Runs `f` with `timezone` as the default timezone of the `datetime` filter.
+///
+/// Rendering is synchronous, so a thread local is enough to pass the caller's
+/// timezone to the filters without adding it to every template's params.
+pub(crate) fn with_timezone<T>(timezone: &str, f: impl FnOnce() -> T) -> T {
+  let tz = Timezone::parse(timezone);
+  let prev = RENDER_TIMEZONE.with(|cell| cell.replace(tz));
+  let _restore =
+    scopeguard::guard(prev, |prev| RENDER_TIMEZONE.with(|cell| *cell.borrow_mut() = prev));
+  f()
+}
+
+/// Formats `dt` in `timezone` with a chrono `format`, month and day names in `lang`.
+///
+/// Fails if `format` has an invalid specifier.
+pub fn format_datetime(
+  lang: &str,
+  dt: DateTime<Utc>,
+  timezone: Option<Timezone>,
+  format: &str,
+  digits: Option<Digits>,
+) -> Result<String, fmt::Error> {
+  let locale = chrono_locale(lang);
+  let mut s = String::new();
+  match timezone {
+    Some(Timezone::Named(tz)) => {
+      write!(s, "{}", dt.with_timezone(&tz).format_localized(format, locale))?
+    }
+    Some(Timezone::Offset(o)) => {
+      write!(s, "{}", dt.with_timezone(&o).format_localized(format, locale))?
+    }
+    None => write!(s, "{}", dt.format_localized(format, locale))?,
+  };
+  Ok(NumberLocale::for_language(lang).with_digits(digits).digits.localize(&s))
+}
+
+fn chrono_locale(lang: &str) -> Locale {
+  let lang = lang.replace('-', "_");
+  let primary = lang.split('_').next().unwrap_or_default();
+  let default_region = match primary {
+    "ar" => "ar_EG",
+    "en" => "en_US",
+    "fa" => "fa_IR",
+    "ja" => "ja_JP",
+    "ko" => "ko_KR",
+    "zh" => "zh_CN",
+    "uk" => "uk_UA",
+    "sv" => "sv_SE",
+    "cs" => "cs_CZ",
+    _ => "",
+  };
+  let same_region = format!("{primary}_{}", primary.to_ascii_uppercase());
+  [lang.as_str(), default_region, &same_region]
+    .into_iter()
+    .find_map(|l| Locale::try_from(l).ok())
+    .unwrap_or(Locale::en_US)
+}
+
+fn parse_datetime(value: &Value) -> Option<DateTime<Utc>> {
+  match value {
+    Value::Number(n) => DateTime::from_timestamp(n.as_i64()?, 0),
+    Value::String(s) => DateTime::parse_from_rfc3339(s)
+      .map(|dt| dt.with_timezone(&Utc))
+      .ok()
+      .or_else(|| {
+        ["%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M:%S%.f"]
+          .iter()
+          .find_map(|f| NaiveDateTime::parse_from_str(s, f).ok())
+          .map(|dt| dt.and_utc())
+      })
+      .or_else(|| {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").ok()?.and_hms_opt(0, 0, 0).map(|dt| dt.and_utc())
+      }),
+    _ => None,
+  }
+}
+
+fn as_f64(value: &Value) -> Option<f64> {
+  match value {
+    Value::Number(n) => n.as_f64(),
+    Value::String(s) => s.trim().parse().ok(),
+    _ => None,
+  }
+}
+
+fn as_i64(value: &Value) -> Option<i64> {
+  match value {
+    Value::Number(n) => n.as_i64(),
+    Value::String(s) => s.trim().parse().ok(),
+    _ => None,
+  }
+}
+
+type Args = HashMap<String, Value>;
+
+fn digits_arg(filter: &str, args: &Args) -> tera::Result<Option<Digits>> {
+  match args.get("digits") {
+    None => Ok(None),
+    Some(v) => v.as_str().and_then(Digits::parse).map(Some).ok_or_else(|| {
+      tera::Error::msg(format!("{filter}: `digits` must be one of latn, arab, arabext"))
+    }),
+  }
+}
+
+fn usize_arg(filter: &str, args: &Args, name: &str, default: usize) -> tera::Result<usize> {
+  match args.get(name) {
+    None => Ok(default),
+    Some(v) => v
+      .as_u64()
+      .map(|n| n as usize)
+      .ok_or_else(|| tera::Error::msg(format!("{filter}: `{name}` must be a positive integer"))),
+  }
+}
+
+fn value_error(filter: &str, value: &Value) -> tera::Error {
+  tera::Error::msg(format!("{filter}: unsupported value {value}"))
+}
+
+/// Registers the locale-aware filters of `lang` on `tera`:
+///
+/// - `number(decimals=0, digits=?)`: `{{ 1234567 | number }}` → `1,234,567`, `١٬٢٣٤٬٥٦٧` in Arabic
+/// - `currency(code="USD", minor=false, digits=?)`: `{{ 1999 | currency(code="EUR", minor=true) }}`
+///   → `19,99 €` in German. `minor=true` takes the amount in minor units (cents)
+/// - `percent(decimals=0, digits=?)`: `{{ 0.25 | percent }}` → `25%`
+/// - `datetime(format="%Y-%m-%d %H:%M", tz=?, digits=?)`: RFC 3339 strings, `YYYY-MM-DD[ HH:MM:SS]`
+///   or unix seconds, in `tz` or else the caller's timezone (`Translator::tr_ctx`), else UTC
+///
+/// `digits` overrides the digits of the language: `latn`, `arab` or `arabext`.
+pub(crate) fn register_filters(tera: &mut tera::Tera, lang: &str) {
+  let locale = NumberLocale::for_language(lang);
+
+  tera.register_filter("number", move |value: &Value, args: &Args| {
+    let n = as_f64(value).ok_or_else(|| value_error("number", value))?;
+    let decimals = usize_arg("number", args, "decimals", 0)?;
+    Ok(Value::String(locale.with_digits(digits_arg("number", args)?).number(n, decimals)))
+  });
+
+  tera.register_filter("percent", move |value: &Value, args: &Args| {
+    let n = as_f64(value).ok_or_else(|| value_error("percent", value))?;
+    let decimals = usize_arg("percent", args, "decimals", 0)?;
+    Ok(Value::String(locale.with_digits(digits_arg("percent", args)?).percent(n, decimals)))
+  });
+
+  tera.register_filter("currency", move |value: &Value, args: &Args| {
+    let code = match args.get("code") {
+      Some(Value::String(code)) => code.as_str(),
+      Some(_) => return Err(tera::Error::msg("currency: `code` must be a string")),
+      None => "USD",
+    };
+    let locale = locale.with_digits(digits_arg("currency", args)?);
+    let s = if args.get("minor").and_then(Value::as_bool).unwrap_or(false) {
+      locale.currency_minor(as_i64(value).ok_or_else(|| value_error("currency", value))?, code)
+    } else {
+      locale.currency(as_f64(value).ok_or_else(|| value_error("currency", value))?, code)
+    };
+    Ok(Value::String(s))
+  });
+
+  let lang = lang.to_string();
+  tera.register_filter("datetime", move |value: &Value, args: &Args| {
+    let dt = parse_datetime(value).ok_or_else(|| value_error("datetime", value))?;
+    let format = args.get("format").and_then(Value::as_str).unwrap_or("%Y-%m-%d %H:%M");
+    let timezone = match args.get("tz").and_then(Value::as_str) {
+      Some(tz) => Some(
+        Timezone::parse(tz)
+          .ok_or_else(|| tera::Error::msg(format!("datetime: unknown timezone {tz}")))?,
+      ),
+      None => RENDER_TIMEZONE.with(|cell| *cell.borrow()),
+    };
+    let digits = digits_arg("datetime", args)?;
+    format_datetime(&lang, dt, timezone, format, digits)
+      .map(Value::String)
+      .map_err(|_| tera::Error::msg(format!("datetime: invalid format {format}")))
+  });
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_number_locale() {
+    let en = NumberLocale::for_language("en-US");
+    assert_eq!(en.number(1234567.891, 2), "1,234,567.89");
+    assert_eq!(en.number(-999.5, 0), "-1,000");
+    assert_eq!(en.number(-0.001, 2), "0.00");
+    assert_eq!(en.percent(0.256, 1), "25.6%");
+    assert_eq!(en.currency_minor(-123456, "USD"), "-$1,234.56");
+    assert_eq!(en.currency_minor(5, "KWD"), "KWD\u{A0}0.005");
+    assert_eq!(en.currency(1234.56, "JPY"), "¥1,235");
+
+    let ar = NumberLocale::for_language("ar-EG");
+    assert_eq!(ar.number(1234567.5, 1), "١٬٢٣٤٬٥٦٧٫٥");
+    assert_eq!(ar.currency_minor(1999, "EGP"), "١٩٫٩٩\u{A0}EGP");
+    assert_eq!(NumberLocale::for_language("ar-MA").number(1234.5, 1), "1.234,5");
+    assert_eq!(NumberLocale::for_language("fa").number(12.0, 0), "۱۲");
+    assert_eq!(NumberLocale::for_language("de").currency_minor(1999, "EUR"), "19,99\u{A0}€");
+  }
+
+  #[test]
+  fn test_timezone() {
+    assert_eq!(Timezone::parse("Africa/Cairo"), Some(Timezone::Named(Tz::Africa__Cairo)));
+    assert_eq!(
+      Timezone::parse("-03:30"),
+      FixedOffset::west_opt(3 * 3600 + 1800).map(Timezone::Offset)
+    );
+    assert_eq!(Timezone::parse("Mars/Olympus"), None);
+  }
+
+  #[test]
+  fn test_filters() {
+    let render = |lang: &str, tz: &str, src: &str, params: Value| {
+      let mut tera = tera::Tera::default();
+      register_filters(&mut tera, lang);
+      tera.add_raw_template("t", src).unwrap();
+      let ctx = tera::Context::from_value(params).unwrap();
+      with_timezone(tz, || tera.render("t", &ctx))
+    };
+    let params = serde_json::json!({ "n": 1234.56, "cents": 250000, "at": "2025-01-31T22:30:00Z" });
+
+    let out = render(
+      "en",
+      "",
+      "{{ n | number(decimals=1) }} {{ cents | currency(code='USD', minor=true) }}",
+      params.clone(),
+    );
+    assert_eq!(out.unwrap(), "1,234.6 $2,500.00");
+    let src =
+      "{{ n | number(digits='latn') }} {{ 0.5 | percent(digits='latn') }} {{ 0.5 | percent }}";
+    assert_eq!(render("ar", "", src, params.clone()).unwrap(), "1,235 50% ٥٠٪\u{061C}");
+    let src = "{{ n | number(decimals=1, digits='arab') }} {{ n | number(digits='latn') }}";
+    assert_eq!(render("en", "", src, params.clone()).unwrap(), "١٬٢٣٤٫٦ 1,235");
+    assert_eq!(render("fr", "", src, params.clone()).unwrap(), "١٬٢٣٤٫٦ 1\u{202F}235");
+
+    let src = "{{ at | datetime(format='%d %B %Y %H:%M') }}";
+    assert_eq!(
+      render("en", "Africa/Cairo", src, params.clone()).unwrap(),
+      "01 February 2025 00:30"
+    );
+    assert_eq!(render("en", "", src, params.clone()).unwrap(), "31 January 2025 22:30");
+    assert_eq!(
+      render("ar", "Asia/Tokyo", "{{ at | datetime(format='%H:%M') }}", params.clone()).unwrap(),
+      "٠٧:٣٠"
+    );
+    let src = "{{ at | datetime(format='%H:%M', tz='+01:00') }}";
+    assert_eq!(render("en", "Asia/Tokyo", src, params.clone()).unwrap(), "23:30");
+
+    assert!(render("en", "", "{{ 'abc' | number }}", params.clone()).is_err());
+    assert!(render("en", "", "{{ at | datetime(format='%Q') }}", params).is_err());
+  }
+}
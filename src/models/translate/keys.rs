@@ -0,0 +1,27 @@
+use std::collections::HashMap;
+
+use serde_json::Value;
+
+/// The type of a param of a typed translation key
+pub type ParamValue = Value;
+
+/// A translation key generated by `translation_keys!`, with the params its translation needs
+pub trait TypedKey {
+  const ID: &'static str;
+  type Params: TypedParams;
+
+  fn id(&self) -> &'static str {
+    Self::ID
+  }
+}
+
+/// The params of a `TypedKey`, `()` for translations without variables
+pub trait TypedParams {
+  fn into_params(self) -> Option<HashMap<String, ParamValue>>;
+}
+
+impl TypedParams for () {
+  fn into_params(self) -> Option<HashMap<String, ParamValue>> {
+    None
+  }
+}
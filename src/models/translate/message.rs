@@ -0,0 +1,116 @@
+use megacommerce_shared_translate_core::PluralSelector;
+pub(crate) use megacommerce_shared_translate_core::{Message, Part};
+use serde_json::Value;
+
+use super::TranslationError;
+use super::plural::{PluralCategory, PluralOperands, plural_category};
+
+/// Walks the ICU blocks of `msg` with `params` and returns the leaves to render, in order
+pub(crate) fn select_leaves(
+  msg: &Message,
+  lang: &str,
+  params: &Value,
+) -> Result<Vec<usize>, TranslationError> {
+  let mut out = Vec::with_capacity(msg.leaves.len());
+  select(&msg.parts, lang, params, &mut out)?;
+  Ok(out)
+}
+
+fn lookup<'a>(params: &'a Value, var: &str) -> Option<&'a Value> {
+  var.split('.').try_fold(params, |v, key| v.get(key)).filter(|v| !v.is_null())
+}
+
+fn select(
+  parts: &[Part],
+  lang: &str,
+  params: &Value,
+  out: &mut Vec<usize>,
+) -> Result<(), TranslationError> {
+  for part in parts {
+    match part {
+      Part::Text(leaf) => out.push(*leaf),
+      Part::Plural { var, branches } => {
+        let op = match lookup(params, var) {
+          Some(Value::Number(n)) => PluralOperands::parse(&n.to_string()),
+          Some(Value::String(s)) => PluralOperands::parse(s),
+          _ => None,
+        }
+        .ok_or(TranslationError::MissingParams)?;
+        let category = plural_category(lang, &op);
+
+        let branch = branches
+          .iter()
+          .find(|(s, _)| *s == PluralSelector::Exact(op.n))
+          .or_else(|| branches.iter().find(|(s, _)| *s == PluralSelector::Category(category)))
+          .or_else(|| {
+            branches.iter().find(|(s, _)| *s == PluralSelector::Category(PluralCategory::Other))
+          });
+        if let Some((_, parts)) = branch {
+          select(parts, lang, params, out)?;
+        }
+      }
+      Part::Select { var, branches } => {
+        let value = match lookup(params, var).ok_or(TranslationError::MissingParams)? {
+          Value::String(s) => s.clone(),
+          v => v.to_string(),
+        };
+
+        let branch = branches
+          .iter()
+          .find(|(k, _)| *k == value)
+          .or_else(|| branches.iter().find(|(k, _)| k == "other"));
+        if let Some((_, parts)) = branch {
+          select(parts, lang, params, out)?;
+        }
+      }
+    }
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde_json::json;
+
+  fn render(src: &str, lang: &str, params: Value) -> String {
+    let msg = Message::parse(src).unwrap();
+    let leaves = select_leaves(&msg, lang, &params).unwrap();
+    leaves.iter().map(|l| msg.leaves[*l].as_str()).collect()
+  }
+
+  #[test]
+  fn test_plural() {
+    let src = "{count, plural, =0 {No items} one {# item left} other {# items left}}";
+    assert_eq!(render(src, "en", json!({"count": 0})), "No items");
+    assert_eq!(render(src, "en", json!({"count": 1})), "{{ count }} item left");
+    assert_eq!(render(src, "en", json!({"count": 5})), "{{ count }} items left");
+  }
+
+  #[test]
+  fn test_arabic_plural() {
+    let src = "{n, plural, zero {لا منتجات} one {منتج واحد} two {منتجان} few {# منتجات} \
+               many {# منتجًا} other {# منتج}}";
+    assert_eq!(render(src, "ar", json!({"n": 0})), "لا منتجات");
+    assert_eq!(render(src, "ar", json!({"n": 1})), "منتج واحد");
+    assert_eq!(render(src, "ar", json!({"n": 2})), "منتجان");
+    assert_eq!(render(src, "ar", json!({"n": 4})), "{{ n }} منتجات");
+    assert_eq!(render(src, "ar", json!({"n": 15})), "{{ n }} منتجًا");
+    assert_eq!(render(src, "ar", json!({"n": 100})), "{{ n }} منتج");
+  }
+
+  #[test]
+  fn test_select_with_nested_plural() {
+    let src = "{gender, select, female {She has {n, plural, one {# order} other {# orders}}} \
+               other {They have {n, plural, one {# order} other {# orders}}}}.";
+    assert_eq!(render(src, "en", json!({"gender": "female", "n": 1})), "She has {{ n }} order.");
+    assert_eq!(render(src, "en", json!({"gender": "x", "n": 3})), "They have {{ n }} orders.");
+  }
+
+  #[test]
+  fn test_missing_param() {
+    let msg = Message::parse("{n, plural, other {#}}").unwrap();
+    let leaves = select_leaves(&msg, "en", &json!({}));
+    assert!(matches!(leaves, Err(TranslationError::MissingParams)));
+  }
+}
@@ -0,0 +1,655 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use arc_swap::{ArcSwap, ArcSwapOption};
+use megacommerce_proto::{TranslationElement, TranslationElements};
+use serde::Serialize;
+use serde_json::Value;
+use thiserror::Error as ThisError;
+
+use crate::models::context::Context;
+
+use catalog::{Catalog, build_catalog, diff_catalogs};
+use fallback::MissingKeys;
+use format::with_timezone;
+use negotiate::negotiate_language;
+use validate::validate_catalog;
+
+pub use fallback::{FallbackChain, MissingKeyHook};
+pub use files::{
+  TranslationsFormat, export_translations_dir, load_translations_dir, load_translations_file,
+};
+pub use keys::{ParamValue, TypedKey, TypedParams};
+pub use validate::{CatalogIssue, CatalogIssueKind, CatalogReport};
+
+mod catalog;
+mod fallback;
+mod files;
+pub mod format;
+mod keys;
+mod message;
+pub mod negotiate;
+pub mod plural;
+mod validate;
+
+pub type TranslateFunc =
+  Box<dyn Fn(&str, &str, &HashMap<String, Value>) -> Result<String, Box<dyn Error>>>;
+
+fn parse_translations_grpc_respones(
+  data: HashMap<String, TranslationElements>,
+) -> HashMap<String, HashMap<String, String>> {
+  let mut result = HashMap::new();
+  for (lang, elements) in data {
+    let mut lang_map = HashMap::new();
+    for el in elements.trans {
+      lang_map.insert(el.id, el.tr);
+    }
+
+    result.insert(lang, lang_map);
+  }
+
+  result
+}
+
+#[derive(Debug, ThisError)]
+pub enum TranslationError {
+  #[error("translation store is not initialized")]
+  NotInitialized,
+  #[error("translation is missing params")]
+  MissingParams,
+  #[error("translation key is not found: {0}")]
+  KeyNotFound(String),
+  #[error("template render error: {0}")]
+  RenderError(String),
+  #[error("translation file error: {}: {err}", path.display())]
+  File { path: PathBuf, err: String },
+  #[error("invalid translation catalog: {0}")]
+  InvalidCatalog(Box<CatalogReport>),
+}
+
+impl From<tera::Error> for TranslationError {
+  fn from(value: tera::Error) -> Self {
+    TranslationError::RenderError(value.to_string())
+  }
+}
+
+/// A translation key of a specific language
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TranslationKey {
+  pub lang: String,
+  pub id: String,
+}
+
+/// What changed between the old and the new catalog after a reload
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TranslationsDiff {
+  pub added: Vec<TranslationKey>,
+  pub removed: Vec<TranslationKey>,
+  pub changed: Vec<TranslationKey>,
+}
+
+impl TranslationsDiff {
+  pub fn is_empty(&self) -> bool {
+    self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+  }
+}
+
+/// A translation catalog with its own languages.
+///
+/// Every template is compiled once into an immutable catalog that is rendered
+/// concurrently without locks. The catalog is swapped atomically on `reload`,
+/// so `tr` calls never block on a reload and finish against the snapshot they loaded.
+pub struct Translator {
+  state: ArcSwap<TranslatorState>,
+  default_language: String,
+  /// The languages given to `new`, available even without translations of their own
+  languages: Vec<String>,
+  fallback: FallbackChain,
+  missing_keys: MissingKeys,
+  on_missing_key: Option<MissingKeyHook>,
+}
+
+impl fmt::Debug for Translator {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("Translator")
+      .field("default_language", &self.default_language)
+      .field("available_languages", &self.state.load().available_languages)
+      .field("fallback", &self.fallback)
+      .finish_non_exhaustive()
+  }
+}
+
+impl Translator {
+  pub fn new(
+    trans: HashMap<String, TranslationElements>,
+    default_language: String,
+    available_languages: Vec<String>,
+  ) -> Self {
+    let catalog = build_catalog(parse_translations_grpc_respones(trans));
+    let state = TranslatorState::new(catalog, &available_languages);
+
+    Self {
+      state: ArcSwap::from_pointee(state),
+      default_language,
+      languages: available_languages,
+      fallback: FallbackChain::default(),
+      missing_keys: MissingKeys::default(),
+      on_missing_key: None,
+    }
+  }
+
+  /// Sets where `tr` looks for keys missing in the requested language
+  pub fn with_fallback(mut self, fallback: FallbackChain) -> Self {
+    self.fallback = fallback;
+    self
+  }
+
+  /// Sets a hook called with `(lang, id)` for every key missing in the requested language
+  pub fn on_missing_key(mut self, hook: MissingKeyHook) -> Self {
+    self.on_missing_key = Some(hook);
+    self
+  }
+
+  /// How many times each key was missing in the requested language since the
+  /// last `take_missing_keys`, even if a fallback language had it
+  pub fn missing_keys(&self) -> HashMap<TranslationKey, u64> {
+    self.missing_keys.snapshot()
+  }
+
+  /// Same as `missing_keys`, but resets the counters
+  pub fn take_missing_keys(&self) -> HashMap<TranslationKey, u64> {
+    self.missing_keys.take()
+  }
+
+  pub fn default_language(&self) -> &str {
+    &self.default_language
+  }
+
+  /// The languages given to `new`, then the other languages of the current catalog
+  pub fn available_languages(&self) -> Vec<String> {
+    self.state.load().available_languages.clone()
+  }
+
+  /// Resolves a language code or an `Accept-Language` header to one of the
+  /// available languages, see `negotiate_language`
+  pub fn negotiate(&self, accept_language: &str) -> String {
+    let state = self.state.load();
+    negotiate_language(accept_language, &state.available_languages, &self.default_language)
+  }
+
+  /// The current translations, in the shape returned by the translation service
+  pub fn translations(&self) -> HashMap<String, TranslationElements> {
+    self
+      .state
+      .load()
+      .catalog
+      .iter()
+      .map(|(lang, catalog)| {
+        let mut trans: Vec<_> = catalog
+          .messages
+          .iter()
+          .map(|(id, msg)| TranslationElement { id: id.clone(), tr: msg.source.clone() })
+          .collect();
+        trans.sort_by(|a, b| a.id.cmp(&b.id));
+        (lang.clone(), TranslationElements { trans })
+      })
+      .collect()
+  }
+
+  /// Checks every translation for syntax errors, keys missing compared to the default
+  /// language and variables that differ from the default language's translation.
+  pub fn validate(&self) -> CatalogReport {
+    validate_catalog(&self.state.load().catalog, &self.default_language)
+  }
+
+  /// Same as `validate`, plus a missing key issue for each of `ids` that the default
+  /// language doesn't have, e.g. the ids of `store::errors::DB_DEFAULT_TRANSLATIONS`
+  pub fn validate_required(&self, ids: &[&str]) -> CatalogReport {
+    let mut report = self.validate();
+    let state = self.state.load();
+    let default_catalog = state.catalog.get(&self.default_language);
+    for id in ids {
+      if default_catalog.and_then(|c| c.get(id)).is_none() {
+        report.issues.push(CatalogIssue {
+          lang: self.default_language.clone(),
+          id: id.to_string(),
+          kind: CatalogIssueKind::MissingKey,
+        });
+      }
+    }
+    report.issues.sort_by(|a, b| (&a.lang, &a.id).cmp(&(&b.lang, &b.id)));
+    report
+  }
+
+  /// Replaces the catalog with `trans`, keeping the fallback settings. The languages of
+  /// `trans` become available, along with the ones given to `new`.
+  ///
+  /// The current catalog is kept if `trans` has syntax errors. The diff is against the
+  /// catalog that was replaced, also when other reloads run concurrently.
+  pub fn reload(
+    &self,
+    trans: HashMap<String, TranslationElements>,
+  ) -> Result<TranslationsDiff, TranslationError> {
+    let catalog = build_catalog(parse_translations_grpc_respones(trans));
+    let report = validate_catalog(&catalog, &self.default_language);
+    if report.has_errors() {
+      return Err(TranslationError::InvalidCatalog(Box::new(report)));
+    }
+
+    // `rcu` retries if another reload swapped the catalog in the meantime
+    let state = Arc::new(TranslatorState::new(catalog, &self.languages));
+    let mut diff = TranslationsDiff::default();
+    self.state.rcu(|current| {
+      diff = diff_catalogs(&current.catalog, &state.catalog);
+      state.clone()
+    });
+    Ok(diff)
+  }
+
+  /// Same as `tr`, with `timezone` (an IANA name or an offset like `+02:00`) as the
+  /// default timezone of the `datetime` filter
+  pub fn tr_with_timezone<P: Serialize>(
+    &self,
+    lang: &str,
+    timezone: &str,
+    id: &str,
+    params: Option<P>,
+  ) -> Result<String, TranslationError> {
+    with_timezone(timezone, || self.tr(lang, id, params))
+  }
+
+  /// Same as `tr`, with a key generated by `translation_keys!` so that an unknown key
+  /// or missing params fail to compile
+  pub fn tr_typed<K: TypedKey>(
+    &self,
+    lang: &str,
+    _key: K,
+    params: K::Params,
+  ) -> Result<String, TranslationError> {
+    self.tr(lang, K::ID, params.into_params())
+  }
+
+  /// Translates in the language and the timezone of the request
+  pub fn tr_ctx<P: Serialize>(
+    &self,
+    ctx: &Context,
+    id: &str,
+    params: Option<P>,
+  ) -> Result<String, TranslationError> {
+    self.tr_with_timezone(ctx.language(), &ctx.timezone, id, params)
+  }
+
+  pub fn tr<P: Serialize>(
+    &self,
+    lang: &str,
+    id: &str,
+    params: Option<P>,
+  ) -> Result<String, TranslationError> {
+    let state = self.state.load();
+    let catalog = &state.catalog;
+    let lang = match state.available_languages.iter().find(|l| *l == lang) {
+      Some(lang) => Cow::Borrowed(lang.as_str()),
+      None => {
+        Cow::Owned(negotiate_language(lang, &state.available_languages, &self.default_language))
+      }
+    };
+    let params = params
+      .map(|p| serde_json::to_value(&p))
+      .transpose()
+      .map_err(|e| TranslationError::RenderError(e.to_string()))?;
+
+    let find = |lang: &str| catalog.get(lang).filter(|c| c.get(id).is_some());
+    if let Some(lang_catalog) = find(&lang) {
+      return lang_catalog.render(&lang, id, params);
+    }
+
+    self.missing_keys.record(&lang, id);
+    if let Some(hook) = &self.on_missing_key {
+      hook(&lang, id);
+    }
+
+    for fallback in self.fallback.languages_for(&lang, &self.default_language) {
+      if let Some(lang_catalog) = find(&fallback) {
+        return lang_catalog.render(&fallback, id, params);
+      }
+    }
+
+    if self.fallback.id {
+      return Ok(id.to_string());
+    }
+    Err(TranslationError::KeyNotFound(id.to_string()))
+  }
+}
+
+/// A catalog and its languages, swapped together so `tr` never sees the languages of
+/// another catalog
+struct TranslatorState {
+  catalog: Catalog,
+  available_languages: Vec<String>,
+}
+
+impl TranslatorState {
+  fn new(catalog: Catalog, languages: &[String]) -> Self {
+    let mut added: Vec<_> = catalog.keys().filter(|l| !languages.contains(l)).cloned().collect();
+    added.sort();
+    let available_languages = languages.iter().cloned().chain(added).collect();
+    Self { catalog, available_languages }
+  }
+}
+
+/// Adds the `defaults` (id, translation) that `lang` doesn't have to `trans`, before
+/// `Translator::new` or `reload`:
+///
+/// ```ignore
+/// add_missing_translations(&mut trans, "en", DB_DEFAULT_TRANSLATIONS);
+/// ```
+pub fn add_missing_translations(
+  trans: &mut HashMap<String, TranslationElements>,
+  lang: &str,
+  defaults: &[(&str, &str)],
+) {
+  let elements = &mut trans.entry(lang.to_string()).or_default().trans;
+  for (id, tr) in defaults {
+    if !elements.iter().any(|e| e.id == *id) {
+      elements.push(TranslationElement { id: id.to_string(), tr: tr.to_string() });
+    }
+  }
+}
+
+static DEFAULT_TRANSLATOR: ArcSwapOption<Translator> = ArcSwapOption::const_empty();
+
+/// Returns the process-wide translator used by `tr`
+pub fn default_translator() -> Result<Arc<Translator>, TranslationError> {
+  DEFAULT_TRANSLATOR.load_full().ok_or(TranslationError::NotInitialized)
+}
+
+/// Installs `translator` as the process-wide translator used by `tr`
+pub fn set_default_translator(translator: Translator) {
+  DEFAULT_TRANSLATOR.store(Some(Arc::new(translator)));
+}
+
+/// Initializes (or re-initializes) the process-wide translator.
+///
+/// Every translation is validated first. A catalog with syntax errors is rejected with
+/// `InvalidCatalog`, otherwise the report is returned so the warnings (missing keys,
+/// mismatched variables) can be logged.
+///
+/// `_max_pool_size` is kept for compatibility, templates are no longer pooled.
+pub fn translations_init(
+  trans: HashMap<String, TranslationElements>,
+  _max_pool_size: usize,
+  default_language: String,
+  available_languages: Vec<String>,
+) -> Result<CatalogReport, TranslationError> {
+  let translator = Translator::new(trans, default_language, available_languages);
+  let report = translator.validate();
+  if report.has_errors() {
+    return Err(TranslationError::InvalidCatalog(Box::new(report)));
+  }
+
+  set_default_translator(translator);
+  Ok(report)
+}
+
+/// Atomically replaces the translations of the process-wide translator, see `Translator::reload`
+pub fn translations_reload(
+  trans: HashMap<String, TranslationElements>,
+) -> Result<TranslationsDiff, TranslationError> {
+  default_translator()?.reload(trans)
+}
+
+/// Writes the translations of the process-wide translator to `dir`, see `export_translations_dir`
+pub fn translations_export(
+  dir: impl AsRef<Path>,
+  format: TranslationsFormat,
+) -> Result<Vec<PathBuf>, TranslationError> {
+  export_translations_dir(dir, &default_translator()?.translations(), format)
+}
+
+pub fn tr<P: Serialize>(
+  lang: &str,
+  id: &str,
+  params: Option<P>,
+) -> Result<String, TranslationError> {
+  default_translator()?.tr(lang, id, params)
+}
+
+/// Translates with the process-wide translator, see `Translator::tr_typed`
+pub fn tr_typed<K: TypedKey>(
+  lang: &str,
+  key: K,
+  params: K::Params,
+) -> Result<String, TranslationError> {
+  default_translator()?.tr_typed(lang, key, params)
+}
+
+/// Translates with the process-wide translator, see `Translator::tr_ctx`
+pub fn tr_ctx<P: Serialize>(
+  ctx: &Context,
+  id: &str,
+  params: Option<P>,
+) -> Result<String, TranslationError> {
+  default_translator()?.tr_ctx(ctx, id, params)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use megacommerce_proto::TranslationElement;
+  use std::sync::Mutex;
+
+  fn elements(items: &[(&str, &str)]) -> TranslationElements {
+    TranslationElements {
+      trans: items
+        .iter()
+        .map(|(id, tr)| TranslationElement { id: id.to_string(), tr: tr.to_string() })
+        .collect(),
+    }
+  }
+
+  fn key(lang: &str, id: &str) -> TranslationKey {
+    TranslationKey { lang: lang.to_string(), id: id.to_string() }
+  }
+
+  fn translator(trans: &[(&str, &[(&str, &str)])]) -> Translator {
+    let trans = trans.iter().map(|(lang, items)| (lang.to_string(), elements(items))).collect();
+    let langs = vec!["en".to_string(), "ar".to_string(), "ar-EG".to_string()];
+    Translator::new(trans, "en".to_string(), langs)
+  }
+
+  #[test]
+  fn test_translator_tr() {
+    let t = translator(&[
+      ("en", &[("hello", "Hello {{ name }}"), ("bye", "Bye")]),
+      ("ar", &[("hello", "مرحبا {{ name }}")]),
+    ]);
+    let params = HashMap::from([("name", "Sam")]);
+
+    assert_eq!(t.tr("en", "hello", Some(&params)).unwrap(), "Hello Sam");
+    assert_eq!(t.tr("ar", "hello", Some(&params)).unwrap(), "مرحبا Sam");
+    // unknown languages use the default one
+    assert_eq!(t.tr("fr", "hello", Some(&params)).unwrap(), "Hello Sam");
+    assert_eq!(t.tr("ar-EG,ar;q=0.9,en;q=0.8", "hello", Some(&params)).unwrap(), "مرحبا Sam");
+    assert_eq!(t.tr::<()>("en", "bye", None).unwrap(), "Bye");
+    assert!(matches!(t.tr::<()>("en", "hello", None), Err(TranslationError::MissingParams)));
+    assert!(matches!(t.tr::<()>("en", "nope", None), Err(TranslationError::KeyNotFound(_))));
+  }
+
+  #[test]
+  fn test_translator_fallback_chain() {
+    let trans: &[(&str, &[(&str, &str)])] = &[
+      ("en", &[("a", "A en"), ("b", "B en"), ("c", "C en")]),
+      ("ar", &[("a", "A ar"), ("b", "B ar")]),
+      ("ar-EG", &[("a", "A ar-EG")]),
+    ];
+    let missing = Arc::new(Mutex::new(vec![]));
+    let hook_missing = missing.clone();
+    let t = translator(trans).on_missing_key(Arc::new(move |lang: &str, id: &str| {
+      hook_missing.lock().unwrap().push(format!("{lang}:{id}"));
+    }));
+
+    assert_eq!(t.tr::<()>("ar-EG", "a", None).unwrap(), "A ar-EG");
+    assert_eq!(t.tr::<()>("ar-EG", "b", None).unwrap(), "B ar");
+    assert_eq!(t.tr::<()>("ar-EG", "c", None).unwrap(), "C en");
+    assert_eq!(t.tr::<()>("ar-EG", "c", None).unwrap(), "C en");
+    assert!(matches!(t.tr::<()>("ar", "d", None), Err(TranslationError::KeyNotFound(_))));
+
+    assert_eq!(*missing.lock().unwrap(), vec!["ar-EG:b", "ar-EG:c", "ar-EG:c", "ar:d"]);
+    let counts = t.take_missing_keys();
+    assert_eq!(counts[&key("ar-EG", "c")], 2);
+    assert_eq!(counts[&key("ar", "d")], 1);
+    assert!(t.missing_keys().is_empty());
+
+    let t = translator(trans).with_fallback(FallbackChain { id: true, ..FallbackChain::none() });
+    assert_eq!(t.tr::<()>("ar-EG", "b", None).unwrap(), "b");
+  }
+
+  #[test]
+  fn test_translator_plurals() {
+    let t = translator(&[
+      ("en", &[("left", "{count, plural, one {# item} other {# items}} left in {{ store }}")]),
+      (
+        "ar",
+        &[(
+          "left",
+          "{count, plural, zero {لا منتجات} one {منتج واحد} two {منتجان} few {# منتجات} \
+           many {# منتجًا} other {# منتج}}",
+        )],
+      ),
+    ]);
+    let en = |count: i32| {
+      let params = serde_json::json!({"count": count, "store": "Main"});
+      t.tr("en", "left", Some(params)).unwrap()
+    };
+    let ar = |count: i32| t.tr("ar", "left", Some(HashMap::from([("count", count)]))).unwrap();
+
+    assert_eq!(en(1), "1 item left in Main");
+    assert_eq!(en(3), "3 items left in Main");
+    assert_eq!(ar(0), "لا منتجات");
+    assert_eq!(ar(2), "منتجان");
+    assert_eq!(ar(7), "7 منتجات");
+    assert_eq!(ar(11), "11 منتجًا");
+    assert_eq!(ar(102), "102 منتج");
+    assert!(matches!(t.tr::<()>("en", "left", None), Err(TranslationError::MissingParams)));
+  }
+
+  #[test]
+  fn test_translator_concurrent_render() {
+    let t = Arc::new(translator(&[(
+      "en",
+      &[("hello", "Hello {{ name }}"), ("broken", "Hello {{ name")],
+    )]));
+
+    let handles: Vec<_> = (0..8)
+      .map(|i| {
+        let t = t.clone();
+        std::thread::spawn(move || {
+          for _ in 0..100 {
+            let params = HashMap::from([("name", i)]);
+            assert_eq!(t.tr("en", "hello", Some(&params)).unwrap(), format!("Hello {i}"));
+            assert!(matches!(
+              t.tr("en", "broken", Some(&params)),
+              Err(TranslationError::RenderError(_))
+            ));
+          }
+        })
+      })
+      .collect();
+    handles.into_iter().for_each(|h| h.join().unwrap());
+  }
+
+  #[test]
+  fn test_translator_reload() {
+    let t = translator(&[("en", &[("a", "A"), ("b", "B"), ("c", "C")])]);
+
+    let trans = HashMap::from([
+      ("en".to_string(), elements(&[("a", "A"), ("b", "B changed"), ("d", "D")])),
+      ("ar".to_string(), elements(&[("a", "أ")])),
+    ]);
+    let diff = t.reload(trans).unwrap();
+
+    assert_eq!(diff.added, vec![key("ar", "a"), key("en", "d")]);
+    assert_eq!(diff.removed, vec![key("en", "c")]);
+    assert_eq!(diff.changed, vec![key("en", "b")]);
+    assert_eq!(t.tr::<()>("en", "b", None).unwrap(), "B changed");
+    assert!(t.tr::<()>("en", "c", None).is_err());
+
+    // a language added by the reload is available
+    let t = Translator::new(HashMap::new(), "en".to_string(), vec!["en".to_string()]);
+    t.reload(HashMap::from([
+      ("en".to_string(), elements(&[("a", "A")])),
+      ("fr".to_string(), elements(&[("a", "Á")])),
+    ]))
+    .unwrap();
+    assert_eq!(t.available_languages(), vec!["en", "fr"]);
+    assert_eq!(t.tr::<()>("fr", "a", None).unwrap(), "Á");
+    assert_eq!(t.negotiate("fr-CA,en;q=0.5"), "fr");
+
+    let broken = HashMap::from([("en".to_string(), elements(&[("a", "{{ a ")]))]);
+    assert!(matches!(t.reload(broken), Err(TranslationError::InvalidCatalog(r)) if r.has_errors()));
+    assert_eq!(t.tr::<()>("en", "a", None).unwrap(), "A");
+  }
+
+  #[test]
+  fn test_translator_concurrent_reload() {
+    let t = Arc::new(translator(&[("en", &[("init", "I")])]));
+    let handles: Vec<_> = (0..8)
+      .map(|i| {
+        let t = t.clone();
+        std::thread::spawn(move || {
+          let id = format!("k{i}");
+          t.reload(HashMap::from([("en".to_string(), elements(&[(id.as_str(), "K")]))])).unwrap()
+        })
+      })
+      .collect();
+    let diffs: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+
+    // every catalog is replaced once, so the diffs chain without repeating a removal
+    let mut removed: Vec<_> = diffs.iter().flat_map(|d| d.removed.iter().map(|k| &k.id)).collect();
+    removed.sort();
+    removed.dedup();
+    assert_eq!(removed.len(), 8);
+    assert_eq!(diffs.iter().map(|d| d.added.len()).sum::<usize>(), 8);
+    let last = &t.translations()["en"].trans[0].id;
+    assert!(!removed.contains(&last));
+  }
+
+  #[test]
+  fn test_add_missing_translations() {
+    let mut trans = HashMap::from([("en".to_string(), elements(&[("a", "A custom")]))]);
+    add_missing_translations(&mut trans, "en", &[("a", "A"), ("b", "B")]);
+    let t = Translator::new(trans, "en".to_string(), vec!["en".to_string(), "ar".to_string()]);
+    assert_eq!(t.tr::<()>("en", "a", None).unwrap(), "A custom");
+    assert_eq!(t.tr::<()>("ar", "b", None).unwrap(), "B");
+
+    let report = t.validate_required(&["a", "b", "c"]);
+    let missing: Vec<_> = report.warnings().map(|i| (i.lang.as_str(), i.id.as_str())).collect();
+    assert_eq!(missing, vec![("en", "c")]);
+  }
+
+  #[test]
+  fn test_translator_tr_ctx() {
+    let src = "{{ total | currency(code='EGP', minor=true) }} {{ at | datetime(format='%H:%M') }}";
+    let t = translator(&[("en", &[("order.total", src)]), ("ar", &[("order.total", src)])]);
+    let params = serde_json::json!({ "total": 123450, "at": "2025-01-31T22:30:00Z" });
+
+    let mut ctx = Context::default().with_language("ar");
+    ctx.timezone = "Africa/Cairo".to_string();
+    assert_eq!(t.tr_ctx(&ctx, "order.total", Some(&params)).unwrap(), "١٬٢٣٤٫٥٠\u{A0}EGP ٠٠:٣٠");
+    assert_eq!(t.tr("en", "order.total", Some(&params)).unwrap(), "EGP\u{A0}1,234.50 22:30");
+  }
+
+  #[test]
+  fn test_translator_translations() {
+    let t = translator(&[("en", &[("b", "B {{ x }}"), ("a", "A")]), ("ar", &[("a", "أ")])]);
+    let trans = t.translations();
+
+    let ids: Vec<_> = trans["en"].trans.iter().map(|e| (e.id.as_str(), e.tr.as_str())).collect();
+    assert_eq!(ids, vec![("a", "A"), ("b", "B {{ x }}")]);
+    assert!(t.reload(trans).unwrap().is_empty());
+  }
+}
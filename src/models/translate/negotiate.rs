@@ -0,0 +1,116 @@
+/// A language range of an `Accept-Language` header with its quality value
+#[derive(Debug, Clone, PartialEq)]
+pub struct LanguageRange {
+  pub tag: String,
+  pub q: f32,
+}
+
+/// Parses an `Accept-Language` header, e.g. `ar-EG,ar;q=0.9,en;q=0.8`.
+///
+/// Ranges are returned by descending quality, keeping the header order for equal
+/// values. Ranges with `q=0` (not acceptable) or an invalid quality are dropped.
+pub fn parse_accept_language(header: &str) -> Vec<LanguageRange> {
+  let mut ranges: Vec<LanguageRange> = header
+    .split(',')
+    .filter_map(|item| {
+      let mut parts = item.split(';');
+      let tag = parts.next()?.trim().replace('_', "-");
+      if tag.is_empty() {
+        return None;
+      }
+
+      let mut q = 1.0;
+      for param in parts {
+        if let Some((k, v)) = param.split_once('=')
+          && k.trim().eq_ignore_ascii_case("q")
+        {
+          q = v.trim().parse::<f32>().ok().filter(|q| (0.0..=1.0).contains(q))?;
+        }
+      }
+
+      (q > 0.0).then_some(LanguageRange { tag, q })
+    })
+    .collect();
+
+  // sort_by is stable, so equal qualities keep the header order
+  ranges.sort_by(|a, b| b.q.total_cmp(&a.q));
+  ranges
+}
+
+/// Picks the best language out of `available` for an `Accept-Language` header.
+///
+/// Each range is tried in preference order, falling back through its parent
+/// locales (`ar-EG` → `ar`) before the next range. `*` or no match at all
+/// resolve to `default`.
+pub fn negotiate_language(header: &str, available: &[String], default: &str) -> String {
+  // fast path for callers passing one of the available codes
+  if let Some(lang) = available.iter().find(|l| *l == header) {
+    return lang.clone();
+  }
+
+  let find = |tag: &str| available.iter().find(|l| l.replace('_', "-").eq_ignore_ascii_case(tag));
+  if let Some(lang) = find(header.trim()) {
+    return lang.clone();
+  }
+
+  for range in parse_accept_language(header) {
+    if range.tag == "*" {
+      break;
+    }
+
+    let mut tag = range.tag.as_str();
+    loop {
+      if let Some(lang) = find(tag) {
+        return lang.clone();
+      }
+      match tag.rfind('-') {
+        Some(i) => tag = &tag[..i],
+        None => break,
+      }
+    }
+  }
+
+  default.to_string()
+}
+
+/// Returns the parent of a locale, e.g. `ar` for `ar-EG`, `zh-Hant` for `zh-Hant-TW`
+pub fn parent_locale(tag: &str) -> Option<&str> {
+  tag.rfind(['-', '_']).map(|i| &tag[..i])
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn langs(items: &[&str]) -> Vec<String> {
+    items.iter().map(|s| s.to_string()).collect()
+  }
+
+  #[test]
+  fn test_parse_accept_language() {
+    let ranges = parse_accept_language("en;q=0.8, ar-EG,ar;q=0.9,fr;q=0,de;q=abc, *;q=0.1");
+    let tags: Vec<_> = ranges.iter().map(|r| (r.tag.as_str(), r.q)).collect();
+    assert_eq!(tags, vec![("ar-EG", 1.0), ("ar", 0.9), ("en", 0.8), ("*", 0.1)]);
+  }
+
+  #[test]
+  fn test_negotiate_language() {
+    let available = langs(&["en", "ar", "fr-CA"]);
+    let negotiate = |header: &str| negotiate_language(header, &available, "en");
+
+    assert_eq!(negotiate("ar"), "ar");
+    assert_eq!(negotiate("ar-EG,ar;q=0.9,en;q=0.8"), "ar");
+    assert_eq!(negotiate("de-DE,fr-ca;q=0.5"), "fr-CA");
+    assert_eq!(negotiate("de, en;q=0.1"), "en");
+    assert_eq!(negotiate("AR_eg"), "ar");
+    assert_eq!(negotiate("de, *"), "en");
+    assert_eq!(negotiate(""), "en");
+  }
+
+  #[test]
+  fn test_parent_locale() {
+    assert_eq!(parent_locale("zh-Hant-TW"), Some("zh-Hant"));
+    assert_eq!(parent_locale("ar_EG"), Some("ar"));
+    assert_eq!(parent_locale("ar"), None);
+  }
+}
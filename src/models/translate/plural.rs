@@ -0,0 +1,197 @@
+pub use megacommerce_shared_translate_core::PluralCategory;
+
+/// The CLDR plural operands of a number
+///
+/// * `n`: absolute value
+/// * `i`: integer digits of `n`
+/// * `v`: number of visible fraction digits (with trailing zeros)
+/// * `f`: visible fraction digits (with trailing zeros)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PluralOperands {
+  pub n: f64,
+  pub i: u64,
+  pub v: usize,
+  pub f: u64,
+}
+
+impl PluralOperands {
+  /// Parses the operands from the decimal representation of a number,
+  /// so that "1" and "1.0" can select different categories.
+  pub fn parse(s: &str) -> Option<Self> {
+    let s = s.trim().trim_start_matches('-');
+    let n = s.parse::<f64>().ok()?.abs();
+    if !n.is_finite() {
+      return None;
+    }
+
+    match s.split_once('.') {
+      Some((int, frac)) if !frac.contains(['e', 'E']) => {
+        Some(Self { n, i: int.parse().ok()?, v: frac.len(), f: frac.parse().unwrap_or(0) })
+      }
+      // exponent notation or a plain integer
+      _ => Some(Self { n, i: n.trunc() as u64, v: 0, f: 0 }),
+    }
+  }
+
+  pub fn from_f64(n: f64) -> Option<Self> {
+    Self::parse(&n.to_string())
+  }
+}
+
+fn n_is(op: &PluralOperands, value: u64) -> bool {
+  op.n == value as f64
+}
+
+fn n_in(op: &PluralOperands, from: u64, to: u64) -> bool {
+  op.n.fract() == 0.0 && op.n >= from as f64 && op.n <= to as f64
+}
+
+/// Returns the cardinal plural category of `op` for `lang`. Region subtags
+/// are ignored (`ar-EG` uses the `ar` rules), unknown languages use the English rules.
+pub fn plural_category(lang: &str, op: &PluralOperands) -> PluralCategory {
+  use PluralCategory::*;
+
+  let primary = lang.split(['-', '_']).next().unwrap_or(lang).to_ascii_lowercase();
+  let (i, v) = (op.i, op.v);
+  let i10 = i % 10;
+  let i100 = i % 100;
+  let n100 = PluralOperands { n: op.n % 100.0, ..*op };
+
+  match primary.as_str() {
+    "ar" => {
+      if n_is(op, 0) {
+        Zero
+      } else if n_is(op, 1) {
+        One
+      } else if n_is(op, 2) {
+        Two
+      } else if n_in(&n100, 3, 10) {
+        Few
+      } else if n_in(&n100, 11, 99) {
+        Many
+      } else {
+        Other
+      }
+    }
+    "cy" => {
+      if n_is(op, 0) {
+        Zero
+      } else if n_is(op, 1) {
+        One
+      } else if n_is(op, 2) {
+        Two
+      } else if n_is(op, 3) {
+        Few
+      } else if n_is(op, 6) {
+        Many
+      } else {
+        Other
+      }
+    }
+    "he" | "iw" => {
+      if (i == 1 && v == 0) || (i == 0 && v != 0) {
+        One
+      } else if i == 2 && v == 0 {
+        Two
+      } else {
+        Other
+      }
+    }
+    "ru" | "uk" | "be" => {
+      if v != 0 {
+        Other
+      } else if i10 == 1 && i100 != 11 {
+        One
+      } else if (2..=4).contains(&i10) && !(12..=14).contains(&i100) {
+        Few
+      } else {
+        Many
+      }
+    }
+    "pl" => {
+      if v != 0 {
+        Other
+      } else if i == 1 {
+        One
+      } else if (2..=4).contains(&i10) && !(12..=14).contains(&i100) {
+        Few
+      } else {
+        Many
+      }
+    }
+    "fr" | "pt" => {
+      if i == 0 || i == 1 {
+        One
+      } else {
+        Other
+      }
+    }
+    "hi" | "fa" | "bn" => {
+      if i == 0 || n_is(op, 1) {
+        One
+      } else {
+        Other
+      }
+    }
+    "es" | "tr" | "el" | "hu" => {
+      if n_is(op, 1) {
+        One
+      } else {
+        Other
+      }
+    }
+    "ja" | "zh" | "ko" | "id" | "ms" | "th" | "vi" => Other,
+    // en, de, nl, sv, it, ur, ...
+    _ => {
+      if i == 1 && v == 0 {
+        One
+      } else {
+        Other
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use PluralCategory::*;
+
+  fn cat(lang: &str, n: &str) -> PluralCategory {
+    plural_category(lang, &PluralOperands::parse(n).unwrap())
+  }
+
+  #[test]
+  fn test_operands() {
+    let op = PluralOperands::parse("-12.50").unwrap();
+    assert_eq!((op.n, op.i, op.v, op.f), (12.5, 12, 2, 50));
+    assert_eq!(PluralOperands::parse("3").unwrap().v, 0);
+    assert!(PluralOperands::parse("abc").is_none());
+  }
+
+  #[test]
+  fn test_arabic() {
+    assert_eq!(cat("ar", "0"), Zero);
+    assert_eq!(cat("ar", "1"), One);
+    assert_eq!(cat("ar-EG", "2"), Two);
+    assert_eq!(cat("ar", "3"), Few);
+    assert_eq!(cat("ar", "110"), Few);
+    assert_eq!(cat("ar", "11"), Many);
+    assert_eq!(cat("ar", "99"), Many);
+    assert_eq!(cat("ar", "100"), Other);
+    assert_eq!(cat("ar", "1.5"), Other);
+  }
+
+  #[test]
+  fn test_other_languages() {
+    assert_eq!(cat("en", "1"), One);
+    assert_eq!(cat("en", "1.0"), Other);
+    assert_eq!(cat("en-US", "0"), Other);
+    assert_eq!(cat("fr", "0"), One);
+    assert_eq!(cat("ru", "21"), One);
+    assert_eq!(cat("ru", "22"), Few);
+    assert_eq!(cat("ru", "12"), Many);
+    assert_eq!(cat("pl", "25"), Many);
+    assert_eq!(cat("ja", "1"), Other);
+  }
+}
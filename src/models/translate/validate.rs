@@ -0,0 +1,200 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use super::TranslationKey;
+use super::catalog::{Catalog, CompiledMessage};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CatalogIssueKind {
+  /// A plural/select block or a Tera template doesn't parse, the key can't be rendered
+  Syntax(String),
+  /// The key exists in the default language but not in this one
+  MissingKey,
+  /// The variables differ from the ones of the default language's translation
+  VariablesMismatch { expected: BTreeSet<String>, found: BTreeSet<String> },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CatalogIssue {
+  pub lang: String,
+  pub id: String,
+  pub kind: CatalogIssueKind,
+}
+
+impl CatalogIssue {
+  /// Syntax issues are errors, the others are warnings that `tr` recovers from
+  pub fn is_error(&self) -> bool {
+    matches!(self.kind, CatalogIssueKind::Syntax(_))
+  }
+}
+
+impl fmt::Display for CatalogIssue {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let join = |s: &BTreeSet<String>| s.iter().cloned().collect::<Vec<_>>().join(", ");
+    match &self.kind {
+      CatalogIssueKind::Syntax(e) => write!(f, "{}/{}: syntax error: {}", self.lang, self.id, e),
+      CatalogIssueKind::MissingKey => write!(f, "{}/{}: missing translation", self.lang, self.id),
+      CatalogIssueKind::VariablesMismatch { expected, found } => write!(
+        f,
+        "{}/{}: variables [{}] don't match the default language's [{}]",
+        self.lang,
+        self.id,
+        join(found),
+        join(expected)
+      ),
+    }
+  }
+}
+
+/// The result of validating a translation catalog
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CatalogReport {
+  pub issues: Vec<CatalogIssue>,
+  /// The variables each translation uses, from both Tera tags and plural/select blocks
+  pub variables: BTreeMap<TranslationKey, BTreeSet<String>>,
+}
+
+impl CatalogReport {
+  pub fn has_errors(&self) -> bool {
+    self.issues.iter().any(|i| i.is_error())
+  }
+
+  pub fn errors(&self) -> impl Iterator<Item = &CatalogIssue> {
+    self.issues.iter().filter(|i| i.is_error())
+  }
+
+  pub fn warnings(&self) -> impl Iterator<Item = &CatalogIssue> {
+    self.issues.iter().filter(|i| !i.is_error())
+  }
+}
+
+impl fmt::Display for CatalogReport {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let errors = self.errors().count();
+    write!(f, "{} errors, {} warnings", errors, self.issues.len() - errors)?;
+    for issue in self.errors() {
+      write!(f, "; {issue}")?;
+    }
+    Ok(())
+  }
+}
+
+pub(crate) fn validate_catalog(catalog: &Catalog, default_language: &str) -> CatalogReport {
+  let mut report = CatalogReport::default();
+
+  for (lang, lang_catalog) in catalog {
+    for (id, msg) in &lang_catalog.messages {
+      let key = TranslationKey { lang: lang.clone(), id: id.clone() };
+      match message_variables(msg) {
+        Ok(vars) => {
+          report.variables.insert(key, vars);
+        }
+        Err(e) => report.issues.push(CatalogIssue {
+          lang: lang.clone(),
+          id: id.clone(),
+          kind: CatalogIssueKind::Syntax(e),
+        }),
+      }
+    }
+  }
+
+  if let Some(default_catalog) = catalog.get(default_language) {
+    for (lang, lang_catalog) in catalog.iter().filter(|(l, _)| *l != default_language) {
+      for id in default_catalog.messages.keys() {
+        let issue = |kind| CatalogIssue { lang: lang.clone(), id: id.clone(), kind };
+        if !lang_catalog.messages.contains_key(id) {
+          report.issues.push(issue(CatalogIssueKind::MissingKey));
+          continue;
+        }
+
+        let vars = |lang: &str| {
+          report.variables.get(&TranslationKey { lang: lang.to_string(), id: id.clone() })
+        };
+        if let (Some(expected), Some(found)) = (vars(default_language), vars(lang))
+          && expected != found
+        {
+          let kind = CatalogIssueKind::VariablesMismatch {
+            expected: expected.clone(),
+            found: found.clone(),
+          };
+          report.issues.push(issue(kind));
+        }
+      }
+    }
+  }
+
+  report.issues.sort_by(|a, b| (&a.lang, &a.id).cmp(&(&b.lang, &b.id)));
+  report
+}
+
+fn message_variables(msg: &CompiledMessage) -> Result<BTreeSet<String>, String> {
+  if let Some(e) = msg.syntax_error.as_ref().or(msg.error.as_ref()) {
+    return Err(e.clone());
+  }
+
+  msg.message.variables()
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::HashMap;
+
+  use super::*;
+  use crate::models::translate::catalog::build_catalog;
+
+  fn report(trans: &[(&str, &[(&str, &str)])]) -> CatalogReport {
+    let parsed = trans
+      .iter()
+      .map(|(lang, items)| {
+        let items = items.iter().map(|(id, tr)| (id.to_string(), tr.to_string())).collect();
+        (lang.to_string(), items)
+      })
+      .collect::<HashMap<_, _>>();
+    validate_catalog(&build_catalog(parsed), "en")
+  }
+
+  fn set(items: &[&str]) -> BTreeSet<String> {
+    items.iter().map(|s| s.to_string()).collect()
+  }
+
+  #[test]
+  fn test_variables() {
+    let r = report(&[(
+      "en",
+      &[(
+        "a",
+        "{% for item in items %}{{ item.name | upper }}{% endfor %}{% if user.vip %}!{% endif %}\
+         {count, plural, one {# {{ unit }}} other {# {{ units }}}}",
+      )],
+    )]);
+    assert!(r.issues.is_empty());
+    let key = TranslationKey { lang: "en".into(), id: "a".into() };
+    assert_eq!(r.variables[&key], set(&["count", "items", "unit", "units", "user"]));
+  }
+
+  #[test]
+  fn test_issues() {
+    let r = report(&[
+      ("en", &[("a", "Hi {{ name }}"), ("b", "Bye"), ("c", "{{ x }}")]),
+      ("ar", &[("a", "مرحبا {{ nme }}"), ("c", "{{ x ")]),
+      ("fr", &[("a", "{n, plural, one {x}}"), ("b", "Au revoir"), ("c", "{{ x }}")]),
+    ]);
+
+    let kinds: Vec<_> =
+      r.issues.iter().map(|i| (i.lang.as_str(), i.id.as_str(), &i.kind)).collect();
+    assert_eq!(kinds.len(), 4);
+    assert_eq!(
+      kinds[0],
+      (
+        "ar",
+        "a",
+        &CatalogIssueKind::VariablesMismatch { expected: set(&["name"]), found: set(&["nme"]) }
+      )
+    );
+    assert_eq!(kinds[1], ("ar", "b", &CatalogIssueKind::MissingKey));
+    assert!(matches!(kinds[2], ("ar", "c", CatalogIssueKind::Syntax(_))));
+    assert!(matches!(kinds[3], ("fr", "a", CatalogIssueKind::Syntax(_))));
+    assert!(r.has_errors());
+    assert_eq!(r.warnings().count(), 2);
+  }
+}
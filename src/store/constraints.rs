@@ -0,0 +1,167 @@
+use std::{collections::HashMap, sync::Arc};
+
+use arc_swap::ArcSwapOption;
+
+use crate::models::errors::{AppErrorError, ErrorType, OptionalParams};
+
+use super::errors::{
+  DBError, MSG_ID_DB_ALREADY_EXISTS, MSG_ID_DB_INVALID, MSG_ID_DB_REQUIRED, parse_key_fields,
+};
+
+/// The field errors reported when a constraint is violated
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConstraintFields {
+  /// The form fields the error is attached to
+  pub fields: Vec<String>,
+  /// The translation id of the error
+  pub id: String,
+  pub params: OptionalParams,
+}
+
+/// Maps the constraint names of a service's tables to the fields and translation ids the
+/// UI shows, so a violated constraint becomes a field error:
+///
+/// ```ignore
+/// let registry = ConstraintRegistry::new()
+///   .register("products_tenant_id_sku_key", &["sku"], "products.sku.taken")
+///   .register("products_price_check", &["price"], "products.price.invalid");
+/// set_constraint_registry(registry);
+///
+/// // errors_internal: {"sku": "products.sku.taken"}
+/// handle_db_error(err, path).to_app_error(ctx, path)
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ConstraintRegistry {
+  constraints: HashMap<String, ConstraintFields>,
+}
+
+impl ConstraintRegistry {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn register(self, constraint: &str, fields: &[&str], id: &str) -> Self {
+    self.register_with_params(constraint, fields, id, None)
+  }
+
+  pub fn register_with_params(
+    mut self,
+    constraint: &str,
+    fields: &[&str],
+    id: &str,
+    params: OptionalParams,
+  ) -> Self {
+    let fields = fields.iter().map(|f| f.to_string()).collect();
+    self
+      .constraints
+      .insert(constraint.to_string(), ConstraintFields { fields, id: id.into(), params });
+    self
+  }
+
+  pub fn get(&self, constraint: &str) -> Option<&ConstraintFields> {
+    self.constraints.get(constraint)
+  }
+
+  /// The field errors of `err`: those registered for its constraint, or else an error on
+  /// the key columns of a unique violation (from its `Key (...)` detail) or on the column of
+  /// a not null violation or of a too long value. `None` if there are none.
+  pub fn field_errors(&self, err: &DBError) -> Option<HashMap<String, AppErrorError>> {
+    let errors: HashMap<_, _> = match err.constraint.as_deref().and_then(|c| self.get(c)) {
+      Some(c) => c
+        .fields
+        .iter()
+        .map(|f| (f.clone(), AppErrorError { id: c.id.clone(), params: c.params.clone() }))
+        .collect(),
+      None => {
+        let (id, fields) = match err.err_type {
+          ErrorType::UniqueViolation => (MSG_ID_DB_ALREADY_EXISTS, parse_key_fields(&err.details)),
+          ErrorType::NotNullViolation => (MSG_ID_DB_REQUIRED, vec![err.column.clone()?]),
+          ErrorType::StringDataRightTruncation => (MSG_ID_DB_INVALID, vec![err.column.clone()?]),
+          _ => return None,
+        };
+        fields
+          .into_iter()
+          .map(|f| (f, AppErrorError { id: id.to_string(), params: None }))
+          .collect()
+      }
+    };
+    Some(errors).filter(|e| !e.is_empty())
+  }
+}
+
+static CONSTRAINT_REGISTRY: ArcSwapOption<ConstraintRegistry> = ArcSwapOption::const_empty();
+
+/// Installs the registry used by `DBError::to_app_error`
+pub fn set_constraint_registry(registry: ConstraintRegistry) {
+  CONSTRAINT_REGISTRY.store(Some(Arc::new(registry)));
+}
+
+/// The registry installed by `set_constraint_registry`
+pub fn constraint_registry() -> Option<Arc<ConstraintRegistry>> {
+  CONSTRAINT_REGISTRY.load_full()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::store::errors::{PgErrorFields, classify_pg_error};
+
+  fn db_error(err_type: ErrorType, constraint: Option<&str>) -> DBError {
+    DBError::new(err_type, "failed".into(), "failed", "ProductsStore.create", "")
+      .with_constraint(constraint.map(str::to_string))
+  }
+
+  #[test]
+  fn test_field_errors() {
+    let registry = ConstraintRegistry::new()
+      .register("products_tenant_id_sku_key", &["sku"], "products.sku.taken")
+      .register("orders_period_excl", &["starts_at", "ends_at"], "orders.period.taken");
+
+    let err = db_error(ErrorType::UniqueViolation, Some("products_tenant_id_sku_key"));
+    let errors = registry.field_errors(&err).unwrap();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors["sku"].id, "products.sku.taken");
+
+    let err = db_error(ErrorType::ExclusionViolation, Some("orders_period_excl"));
+    let errors = registry.field_errors(&err).unwrap();
+    assert_eq!(errors["starts_at"], errors["ends_at"]);
+
+    assert!(registry.field_errors(&db_error(ErrorType::CheckViolation, Some("other"))).is_none());
+    assert!(registry.field_errors(&db_error(ErrorType::UniqueViolation, Some("other"))).is_none());
+
+    // unregistered, the key columns come from the detail
+    let mut err = db_error(ErrorType::UniqueViolation, Some("users_tenant_id_email_key"));
+    err.details = "Key (tenant_id, email)=(7, a@b.c) already exists.".into();
+    let errors = registry.field_errors(&err).unwrap();
+    assert_eq!(errors.len(), 2);
+    assert_eq!(errors["tenant_id"].id, MSG_ID_DB_ALREADY_EXISTS);
+    assert_eq!(errors["email"].id, MSG_ID_DB_ALREADY_EXISTS);
+
+    // unregistered, but the column is known
+    let err = db_error(ErrorType::NotNullViolation, None).with_column("name");
+    assert_eq!(registry.field_errors(&err).unwrap()["name"].id, MSG_ID_DB_REQUIRED);
+
+    let err = db_error(ErrorType::UniqueViolation, Some("products_tenant_id_sku_key"));
+    let app_err = err.to_app_error_with(Default::default(), "products.create", &registry);
+    assert_eq!(app_err.status_code, tonic::Code::AlreadyExists as i32);
+    assert_eq!(app_err.errors_internal.unwrap()["sku"].id, "products.sku.taken");
+  }
+
+  #[test]
+  fn test_composite_key_message() {
+    let err = PgErrorFields {
+      code: "23505".into(),
+      detail: Some("Key (tenant_id, sku)=(7, A-1) already exists.".into()),
+      ..Default::default()
+    };
+    assert_eq!(classify_pg_error(&err).1, "tenant_id, sku combination already exists");
+
+    let err =
+      PgErrorFields { detail: Some("Key (email)=(a@b.c) already exists.".into()), ..err.clone() };
+    assert_eq!(classify_pg_error(&err).1, "email already exists");
+
+    let err =
+      PgErrorFields { detail: Some("Key (lower(email))=(a@b.c) already exists.".into()), ..err };
+    assert_eq!(classify_pg_error(&err).1, "lower(email) already exists");
+  }
+}
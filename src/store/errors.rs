@@ -1,14 +1,48 @@
-use std::{error::Error, fmt, sync::Arc};
+use std::{
+  error::Error,
+  fmt,
+  sync::{Arc, LazyLock},
+};
 
 use regex::Regex;
-use sqlx::error::Error as SqlxError;
+use sqlx::error::{DatabaseError, Error as SqlxError};
 use sqlx::postgres::PgDatabaseError;
 use tonic::Code;
 
 use crate::models::context::Context;
+use crate::models::error_sink::ErrorReport;
 use crate::models::errors::{
-  AppError, AppErrorErrors, ErrorType, InternalError, MSG_ID_ERR_INTERNAL,
+  AppError, AppErrorErrors, ErrorType, InternalError, MSG_ID_ERR_INTERNAL, error_chain,
+  request_fields,
 };
+use crate::utils::retry::{RetryCancelled, Retryable};
+
+use super::constraints::{ConstraintRegistry, constraint_registry};
+
+pub const MSG_ID_DB_NOT_FOUND: &str = "db.not_found.error";
+pub const MSG_ID_DB_ALREADY_EXISTS: &str = "db.already_exists.error";
+pub const MSG_ID_DB_REFERENCE_NOT_FOUND: &str = "db.reference_not_found.error";
+pub const MSG_ID_DB_REQUIRED: &str = "db.required.error";
+pub const MSG_ID_DB_PERMISSION_DENIED: &str = "db.permission_denied.error";
+pub const MSG_ID_DB_UNAVAILABLE: &str = "db.unavailable.error";
+pub const MSG_ID_DB_TIMED_OUT: &str = "db.timed_out.error";
+pub const MSG_ID_DB_INVALID: &str = "db.invalid.error";
+pub const MSG_ID_DB_CONFLICT: &str = "db.conflict.error";
+
+/// The English translations of the `MSG_ID_DB_*` ids, which the services' translations may
+/// not have: add them with `add_missing_translations` and check them with
+/// `Translator::validate_required`
+pub const DB_DEFAULT_TRANSLATIONS: &[(&str, &str)] = &[
+  (MSG_ID_DB_NOT_FOUND, "The requested item is not found"),
+  (MSG_ID_DB_ALREADY_EXISTS, "This item already exists"),
+  (MSG_ID_DB_REFERENCE_NOT_FOUND, "A referenced item is not found"),
+  (MSG_ID_DB_REQUIRED, "This field is required"),
+  (MSG_ID_DB_PERMISSION_DENIED, "You don't have permission to do this"),
+  (MSG_ID_DB_UNAVAILABLE, "The service is temporarily unavailable, please try again"),
+  (MSG_ID_DB_TIMED_OUT, "The request took too long, please try again"),
+  (MSG_ID_DB_INVALID, "This value is invalid"),
+  (MSG_ID_DB_CONFLICT, "The item was changed by another request, please try again"),
+];
 
 #[derive(Debug)]
 pub struct DBError {
@@ -17,6 +51,13 @@ pub struct DBError {
   pub msg: String,
   pub path: String,
   pub details: String,
+  /// The violated constraint, for the constraint violations
+  pub constraint: Option<String>,
+  /// The column that failed to decode or is missing, if known
+  pub column: Option<String>,
+  /// Whether the error is transient (a timed out pool, a lost connection), so that the
+  /// operation may be retried. Defaults to `ErrorType::is_retryable`.
+  pub temp: bool,
 }
 
 impl fmt::Display for DBError {
@@ -37,6 +78,16 @@ impl fmt::Display for DBError {
       parts.push(format!("details: {}", self.details));
     }
 
+    if let Some(constraint) = &self.constraint {
+      parts.push(format!("constraint: {constraint}"));
+    }
+
+    if let Some(column) = &self.column {
+      parts.push(format!("column: {column}"));
+    }
+
+    parts.push(format!("temp: {}", self.temp));
+
     parts.push(format!("err: {}", self.err));
 
     write!(f, "{}", parts.join(", "))
@@ -45,11 +96,49 @@ impl fmt::Display for DBError {
 
 impl From<InternalError> for DBError {
   fn from(e: InternalError) -> Self {
-    DBError { err_type: e.err_type, err: e.err, msg: e.msg, path: e.path, details: "".into() }
+    DBError {
+      temp: e.temp,
+      err_type: e.err_type,
+      err: e.err,
+      msg: e.msg,
+      path: e.path,
+      details: "".into(),
+      constraint: None,
+      column: None,
+    }
+  }
+}
+
+impl Error for DBError {
+  fn source(&self) -> Option<&(dyn Error + 'static)> {
+    Some(self.err.as_ref())
   }
 }
 
-impl Error for DBError {}
+impl From<&DBError> for ErrorReport {
+  fn from(err: &DBError) -> Self {
+    Self {
+      id: err.msg.clone(),
+      path: err.path.clone(),
+      err_type: err.err_type.to_string(),
+      cause: error_chain(err.err.as_ref()).join(": "),
+      request_id: String::new(),
+    }
+  }
+}
+
+impl Retryable for DBError {
+  fn is_retryable(&self) -> bool {
+    self.temp
+  }
+}
+
+impl From<RetryCancelled> for DBError {
+  fn from(e: RetryCancelled) -> Self {
+    let msg = e.to_string();
+    DBError::new(ErrorType::TaskFailed, Box::new(e.clone()), msg, e.path, "")
+  }
+}
 
 impl DBError {
   pub fn new(
@@ -59,9 +148,105 @@ impl DBError {
     path: impl Into<String>,
     details: impl Into<String>,
   ) -> Self {
-    Self { err_type, err, msg: msg.into(), path: path.into(), details: details.into() }
+    Self {
+      temp: err_type.is_retryable(),
+      err_type,
+      err,
+      msg: msg.into(),
+      path: path.into(),
+      details: details.into(),
+      constraint: None,
+      column: None,
+    }
+  }
+
+  pub fn with_column(mut self, column: impl Into<String>) -> Self {
+    self.column = Some(column.into());
+    self
+  }
+
+  fn with_column_option(mut self, column: Option<String>) -> Self {
+    self.column = column;
+    self
+  }
+
+  pub fn with_temp(mut self, temp: bool) -> Self {
+    self.temp = temp;
+    self
+  }
+
+  pub fn with_constraint(mut self, constraint: Option<String>) -> Self {
+    self.constraint = constraint;
+    self
   }
 
+  /// Emits the error as a structured `tracing` event, with the request of `ctx` if given
+  pub fn log(&self, ctx: Option<&Context>) {
+    let (request_id, user_id) = ctx.map(request_fields).unwrap_or_default();
+    let cause_chain = error_chain(self.err.as_ref()).join(": ");
+    tracing::error!(
+      request_id,
+      user_id,
+      err_type = %self.err_type,
+      path = %self.path,
+      msg = %self.msg,
+      details = %self.details,
+      cause_chain,
+      "database error"
+    );
+  }
+
+  /// The user-facing message id of the error, the internal error for what the user
+  /// can't act on
+  pub fn msg_id(&self) -> &'static str {
+    match self.err_type {
+      ErrorType::NoRows | ErrorType::NotFound => MSG_ID_DB_NOT_FOUND,
+      ErrorType::UniqueViolation => MSG_ID_DB_ALREADY_EXISTS,
+      ErrorType::ForeignKeyViolation => MSG_ID_DB_REFERENCE_NOT_FOUND,
+      ErrorType::NotNullViolation => MSG_ID_DB_REQUIRED,
+      ErrorType::Privileges => MSG_ID_DB_PERMISSION_DENIED,
+      ErrorType::Connection | ErrorType::DBConnectionError => MSG_ID_DB_UNAVAILABLE,
+      ErrorType::TimedOut | ErrorType::QueryCanceled => MSG_ID_DB_TIMED_OUT,
+      ErrorType::CheckViolation
+      | ErrorType::InvalidTextRepresentation
+      | ErrorType::NumericValueOutOfRange
+      | ErrorType::StringDataRightTruncation => MSG_ID_DB_INVALID,
+      ErrorType::ExclusionViolation
+      | ErrorType::SerializationFailure
+      | ErrorType::DeadlockDetected
+      | ErrorType::LockNotAvailable => MSG_ID_DB_CONFLICT,
+      _ => MSG_ID_ERR_INTERNAL,
+    }
+  }
+
+  /// Converts to an `AppError` with the status code of `ErrorType::grpc_code` and the
+  /// message id of `msg_id`, e.g. `NotFound` for `NoRows`. The field errors of the violated
+  /// constraint are taken from the registry of `set_constraint_registry`, if any.
+  pub fn to_app_error(self, ctx: Arc<Context>, path: impl Into<String>) -> AppError {
+    match constraint_registry() {
+      Some(registry) => self.to_app_error_with(ctx, path, &registry),
+      None => self.to_app_error_with(ctx, path, &ConstraintRegistry::default()),
+    }
+  }
+
+  /// Same as `to_app_error`, with the field errors of `registry`
+  pub fn to_app_error_with(
+    self,
+    ctx: Arc<Context>,
+    path: impl Into<String>,
+    registry: &ConstraintRegistry,
+  ) -> AppError {
+    let id = self.msg_id();
+    let code = self.err_type.grpc_code();
+    let errors = AppErrorErrors {
+      errors_internal: registry.field_errors(&self),
+      err: Some(self.err),
+      ..Default::default()
+    };
+    AppError::new(ctx, path, id, None, self.details, code.into(), Some(errors))
+  }
+
+  /// Converts to an internal error whatever the type, see `to_app_error`
   pub fn to_app_error_internal(self, ctx: Arc<Context>, path: String) -> AppError {
     let errors = AppErrorErrors { err: Some(self.err), ..Default::default() };
     AppError::new(
@@ -76,44 +261,92 @@ impl DBError {
   }
 }
 
+/// The fields of a Postgres error that `handle_db_error` reads
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PgErrorFields {
+  /// The SQLSTATE code
+  pub code: String,
+  pub message: String,
+  pub detail: Option<String>,
+  pub constraint: Option<String>,
+  pub table: Option<String>,
+  pub column: Option<String>,
+}
+
+impl From<&PgDatabaseError> for PgErrorFields {
+  fn from(err: &PgDatabaseError) -> Self {
+    Self {
+      code: err.code().to_string(),
+      message: err.message().to_string(),
+      detail: err.detail().map(str::to_string),
+      constraint: err.constraint().map(str::to_string),
+      table: err.table().map(str::to_string),
+      column: err.column().map(str::to_string),
+    }
+  }
+}
+
+impl From<&dyn DatabaseError> for PgErrorFields {
+  fn from(err: &dyn DatabaseError) -> Self {
+    if let Some(pg_err) = err.try_downcast_ref::<PgDatabaseError>() {
+      return pg_err.into();
+    }
+    Self {
+      code: err.code().map(|c| c.into_owned()).unwrap_or_default(),
+      message: err.message().to_string(),
+      constraint: err.constraint().map(str::to_string),
+      table: err.table().map(str::to_string),
+      ..Default::default()
+    }
+  }
+}
+
+/// The type and the message of a Postgres error, from its SQLSTATE code
+pub fn classify_pg_error(err: &PgErrorFields) -> (ErrorType, String) {
+  let constraint = || parse_constraint_name(err).unwrap_or_else(|| "constraint".to_string());
+  match err.code.as_str() {
+    // Constraint violations
+    "23505" => (ErrorType::UniqueViolation, parse_duplicate_field_db_error(err)),
+    "23503" => (ErrorType::ForeignKeyViolation, "referenced record is not found".to_string()),
+    "23502" => {
+      (ErrorType::NotNullViolation, format!("{} cannot be null", parse_db_field_name(err)))
+    }
+    "23514" => (ErrorType::CheckViolation, format!("{} is violated", constraint())),
+    "23P01" => (
+      ErrorType::ExclusionViolation,
+      format!("conflicts with an existing record: {}", constraint()),
+    ),
+    // Concurrency, the transaction can be retried
+    "40001" => (ErrorType::SerializationFailure, "concurrent update, try again".to_string()),
+    "40P01" => (ErrorType::DeadlockDetected, "deadlock detected".to_string()),
+    "55P03" => (ErrorType::LockNotAvailable, "could not obtain a lock".to_string()),
+    "57014" => (ErrorType::QueryCanceled, "the query was canceled".to_string()),
+    // Invalid values
+    "22P02" => (ErrorType::InvalidTextRepresentation, "invalid input syntax".to_string()),
+    "22003" => (ErrorType::NumericValueOutOfRange, "numeric value is out of range".to_string()),
+    "22001" => {
+      (ErrorType::StringDataRightTruncation, format!("{} is too long", parse_db_field_name(err)))
+    }
+    // Connection/availability errors
+    code if code.starts_with("08") => {
+      (ErrorType::Connection, "database connection exception".to_string())
+    }
+    // Permission errors
+    "42501" => (ErrorType::Privileges, "insufficient permissions to perform an action".to_string()),
+    _ => (ErrorType::Internal, "database error".to_string()),
+  }
+}
+
 pub fn handle_db_error(err: SqlxError, path: &str) -> DBError {
   match err {
     SqlxError::Database(db_err) => {
-      let pg_err = db_err.downcast_ref::<PgDatabaseError>();
-
-      // Extract details before moving db_err into the Box
-      let details = pg_err.detail().unwrap_or("").to_string();
-      let msg = match pg_err.code() {
-        // Constraint violations
-        "23505" => {
-          // unique_violation
-          parse_duplicate_field_db_error(pg_err)
-        }
-        "23503" => {
-          // foreign_key_violation
-          "referenced record is not found".to_string()
-        }
-        "23502" => {
-          // not_null_violation
-          format!("{} cannot be null", parse_db_field_name(pg_err))
-        }
-        // Connection/availability errors
-        "08000" | "08003" | "08006" => "database connection exception".to_string(),
-        // Permission errors
-        "42501" => "insufficient permissions to perform an action".to_string(),
-        _ => "database error".to_string(),
-      };
-
-      let err_type = match pg_err.code() {
-        "23505" => ErrorType::UniqueViolation,
-        "23503" => ErrorType::ForeignKeyViolation,
-        "23502" => ErrorType::NotNullViolation,
-        "08000" | "08003" | "08006" => ErrorType::Connection,
-        "42501" => ErrorType::Privileges,
-        _ => ErrorType::Internal,
-      };
+      let fields = PgErrorFields::from(db_err.as_ref());
+      let (err_type, msg) = classify_pg_error(&fields);
+      let details = fields.detail.clone().unwrap_or_default();
 
       DBError::new(err_type, Box::new(SqlxError::Database(db_err)), msg, path, details)
+        .with_constraint(parse_constraint_name(&fields))
+        .with_column_option(parse_column_name(&fields))
     }
 
     SqlxError::RowNotFound => DBError::new(
@@ -124,30 +357,272 @@ pub fn handle_db_error(err: SqlxError, path: &str) -> DBError {
       "",
     ),
 
+    // The pool and the connection
+    SqlxError::PoolTimedOut => {
+      let msg = "timed out waiting for a database connection";
+      DBError::new(ErrorType::TimedOut, Box::new(err), msg, path, "").with_temp(true)
+    }
+    SqlxError::PoolClosed => {
+      let msg = "the database connection pool is closed";
+      DBError::new(ErrorType::Connection, Box::new(err), msg, path, "").with_temp(false)
+    }
+    SqlxError::Io(_) | SqlxError::WorkerCrashed => {
+      let msg = "lost the database connection";
+      DBError::new(ErrorType::Connection, Box::new(err), msg, path, "").with_temp(true)
+    }
+    SqlxError::Tls(_) => {
+      let msg = "failed to establish a TLS connection to the database";
+      DBError::new(ErrorType::Connection, Box::new(err), msg, path, "").with_temp(false)
+    }
+    SqlxError::Configuration(_) => DBError::new(
+      ErrorType::ConfigError,
+      Box::new(err),
+      "invalid database configuration",
+      path,
+      "",
+    ),
+
+    // The rows don't match the types they're read into
+    SqlxError::ColumnDecode { ref index, .. } => {
+      // sqlx formats the index with `Debug`, a name comes quoted
+      let column = index.trim_matches('"').to_string();
+      let msg = format!("failed to decode the column {column}");
+      DBError::new(ErrorType::JsonUnmarshal, Box::new(err), msg, path, "").with_column(column)
+    }
+    SqlxError::ColumnNotFound(ref name) => {
+      let column = name.clone();
+      let msg = format!("the column {column} is not found");
+      DBError::new(ErrorType::Internal, Box::new(err), msg, path, "").with_column(column)
+    }
+    SqlxError::Decode(_) => {
+      DBError::new(ErrorType::JsonUnmarshal, Box::new(err), "failed to decode a value", path, "")
+    }
+    SqlxError::Encode(_) => {
+      DBError::new(ErrorType::JsonMarshal, Box::new(err), "failed to encode a value", path, "")
+    }
+    SqlxError::Protocol(_) => {
+      let msg = "unexpected data from the database";
+      DBError::new(ErrorType::Internal, Box::new(err), msg, path, "")
+    }
+
     _ => DBError::new(ErrorType::Internal, Box::new(err), "database error", path, ""),
   }
 }
 
-// Extract the duplicate field from error detail
-// Example: "Key (email)=(test@example.com) already exists.
-fn parse_duplicate_field_db_error(err: &PgDatabaseError) -> String {
-  if let Some(detail) = err.detail() {
-    if let Some(parts) = detail.split(")=(").next() {
-      let field = parts.trim_start_matches("Key (");
-      return format!("{} already exists", field);
+static CONSTRAINT_NAME: LazyLock<Regex> =
+  LazyLock::new(|| Regex::new(r#"constraint "(.+?)""#).unwrap());
+
+// The name of the violated constraint, sent by Postgres or else in the message
+// Example: "new row for relation \"products\" violates check constraint \"products_price_check\""
+fn parse_constraint_name(err: &PgErrorFields) -> Option<String> {
+  err.constraint.clone().or_else(|| {
+    CONSTRAINT_NAME.captures(&err.message).and_then(|c| c.get(1)).map(|m| m.as_str().to_string())
+  })
+}
+
+// The duplicated key, from the error detail
+// Example: "Key (tenant_id, sku)=(7, A-1) already exists."
+fn parse_duplicate_field_db_error(err: &PgErrorFields) -> String {
+  let detail = err.detail.clone().unwrap_or_default();
+  match parse_key_fields(&detail).as_slice() {
+    [] => detail,
+    [field] => format!("{field} already exists"),
+    fields => format!("{} combination already exists", fields.join(", ")),
+  }
+}
+
+/// The columns of the key in the detail of a constraint violation, e.g. `tenant_id` and
+/// `sku` for `Key (tenant_id, sku)=(7, A-1) already exists.`. An expression of an index
+/// (`lower(email)`) is kept whole.
+pub fn parse_key_fields(detail: &str) -> Vec<String> {
+  let Some(rest) = detail.strip_prefix("Key (") else { return vec![] };
+  let mut fields = vec![];
+  let (mut depth, mut start) = (0, 0);
+  for (i, c) in rest.char_indices() {
+    match c {
+      '(' => depth += 1,
+      ')' if depth > 0 => depth -= 1,
+      ',' | ')' if depth == 0 => {
+        fields.push(rest[start..i].trim().to_string());
+        if c == ')' {
+          return fields;
+        }
+        start = i + 1;
+      }
+      _ => {}
     }
   }
-  err.detail().unwrap_or("").to_string()
+  vec![]
 }
 
-// Extract field name from error message
+static COLUMN_NAME: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"column "(.+?)""#).unwrap());
+
+// The column of the error, sent by Postgres or else in the message
 // Example: "null value in column \"email\" violates not-null constraint
-fn parse_db_field_name(err: &PgDatabaseError) -> String {
-  let re = Regex::new(r#"column "(.+?)""#).unwrap();
-  if let Some(captures) = re.captures(err.message()) {
-    if let Some(match_) = captures.get(1) {
-      return match_.as_str().to_string();
+fn parse_column_name(err: &PgErrorFields) -> Option<String> {
+  err.column.clone().or_else(|| {
+    COLUMN_NAME.captures(&err.message).and_then(|c| c.get(1)).map(|m| m.as_str().to_string())
+  })
+}
+
+fn parse_db_field_name(err: &PgErrorFields) -> String {
+  parse_column_name(err).unwrap_or_else(|| "field".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_to_app_error() {
+    let ctx = Arc::new(Context::default());
+    let err = handle_db_error(SqlxError::RowNotFound, "ProductsStore.get");
+    assert_eq!(err.msg_id(), MSG_ID_DB_NOT_FOUND);
+    let app_err = err.to_app_error(ctx.clone(), "products.get");
+    assert_eq!(app_err.status_code, Code::NotFound as i32);
+    assert_eq!(app_err.id, MSG_ID_DB_NOT_FOUND);
+    assert!(app_err.error.is_some());
+
+    let err = handle_db_error(SqlxError::Protocol("unexpected".into()), "ProductsStore.get");
+    let app_err = err.to_app_error(ctx, "products.get");
+    assert_eq!(app_err.status_code, Code::Internal as i32);
+    assert_eq!(app_err.id, MSG_ID_ERR_INTERNAL);
+  }
+
+  #[test]
+  fn test_default_translations() {
+    use crate::models::translate::{Translator, add_missing_translations};
+
+    let ids = [
+      MSG_ID_DB_NOT_FOUND,
+      MSG_ID_DB_ALREADY_EXISTS,
+      MSG_ID_DB_REFERENCE_NOT_FOUND,
+      MSG_ID_DB_REQUIRED,
+      MSG_ID_DB_PERMISSION_DENIED,
+      MSG_ID_DB_UNAVAILABLE,
+      MSG_ID_DB_TIMED_OUT,
+      MSG_ID_DB_INVALID,
+      MSG_ID_DB_CONFLICT,
+    ];
+    let mut trans = Default::default();
+    add_missing_translations(&mut trans, "en", DB_DEFAULT_TRANSLATIONS);
+    let t = Translator::new(trans, "en".to_string(), vec!["en".to_string()]);
+    let report = t.validate_required(&ids);
+    assert!(report.issues.is_empty(), "{report}: {:?}", report.issues);
+    assert_eq!(
+      t.tr::<()>("en", MSG_ID_DB_NOT_FOUND, None).unwrap(),
+      "The requested item is not found"
+    );
+  }
+
+  fn pg_error(code: &str, message: &str) -> PgErrorFields {
+    PgErrorFields { code: code.into(), message: message.into(), ..Default::default() }
+  }
+
+  #[test]
+  fn test_classify_pg_error() {
+    let cases = [
+      ("23514", ErrorType::CheckViolation, false),
+      ("23P01", ErrorType::ExclusionViolation, false),
+      ("40001", ErrorType::SerializationFailure, true),
+      ("40P01", ErrorType::DeadlockDetected, true),
+      ("55P03", ErrorType::LockNotAvailable, true),
+      ("57014", ErrorType::QueryCanceled, false),
+      ("22P02", ErrorType::InvalidTextRepresentation, false),
+      ("22003", ErrorType::NumericValueOutOfRange, false),
+      ("22001", ErrorType::StringDataRightTruncation, false),
+      ("23505", ErrorType::UniqueViolation, false),
+      ("08001", ErrorType::Connection, true),
+      ("XX000", ErrorType::Internal, false),
+    ];
+    for (code, err_type, retryable) in cases {
+      let (got, _) = classify_pg_error(&pg_error(code, "error"));
+      assert_eq!(got, err_type, "{code}");
+      assert_eq!(got.is_retryable(), retryable, "{code}");
+    }
+  }
+
+  #[test]
+  fn test_classify_pg_error_messages() {
+    let err = pg_error(
+      "23514",
+      r#"new row for relation "products" violates check constraint "products_price_check""#,
+    );
+    assert_eq!(parse_constraint_name(&err).as_deref(), Some("products_price_check"));
+    assert_eq!(classify_pg_error(&err).1, "products_price_check is violated");
+
+    // the constraint sent by postgres wins
+    let err = PgErrorFields { constraint: Some("no_overlap".into()), ..pg_error("23P01", "") };
+    assert_eq!(parse_constraint_name(&err).as_deref(), Some("no_overlap"));
+
+    let err = PgErrorFields { column: Some("sku".into()), ..pg_error("22001", "value too long") };
+    assert_eq!(classify_pg_error(&err).1, "sku is too long");
+  }
+
+  // a database error of another driver, which only has the generic fields
+  #[derive(Debug)]
+  struct OtherDbError;
+
+  impl fmt::Display for OtherDbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+      write!(f, "violates check constraint \"stock_positive\"")
     }
   }
-  "field".to_string()
+
+  impl Error for OtherDbError {}
+
+  impl DatabaseError for OtherDbError {
+    fn message(&self) -> &str {
+      "violates check constraint \"stock_positive\""
+    }
+    fn code(&self) -> Option<std::borrow::Cow<'_, str>> {
+      Some("23514".into())
+    }
+    fn as_error(&self) -> &(dyn Error + Send + Sync + 'static) {
+      self
+    }
+    fn as_error_mut(&mut self) -> &mut (dyn Error + Send + Sync + 'static) {
+      self
+    }
+    fn into_error(self: Box<Self>) -> Box<dyn Error + Send + Sync + 'static> {
+      self
+    }
+    fn kind(&self) -> sqlx::error::ErrorKind {
+      sqlx::error::ErrorKind::CheckViolation
+    }
+  }
+
+  #[test]
+  fn test_handle_db_error() {
+    let err = handle_db_error(SqlxError::Database(Box::new(OtherDbError)), "InventoryStore.take");
+    assert_eq!(err.err_type, ErrorType::CheckViolation);
+    assert_eq!(err.constraint.as_deref(), Some("stock_positive"));
+    assert_eq!(err.msg_id(), MSG_ID_DB_INVALID);
+  }
+
+  #[test]
+  fn test_handle_db_error_non_database() {
+    let cases = [
+      (SqlxError::PoolTimedOut, ErrorType::TimedOut, true),
+      (SqlxError::PoolClosed, ErrorType::Connection, false),
+      (SqlxError::Io(std::io::Error::other("reset")), ErrorType::Connection, true),
+      (SqlxError::WorkerCrashed, ErrorType::Connection, true),
+      (SqlxError::Tls("bad certificate".into()), ErrorType::Connection, false),
+      (SqlxError::Protocol("unexpected".into()), ErrorType::Internal, false),
+      (SqlxError::Decode("invalid utf-8".into()), ErrorType::JsonUnmarshal, false),
+    ];
+    for (err, err_type, temp) in cases {
+      let err = handle_db_error(err, "ProductsStore.list");
+      assert_eq!((&err.err_type, err.temp), (&err_type, temp), "{err}");
+      assert_eq!(err.is_retryable(), temp);
+    }
+
+    let decode =
+      SqlxError::ColumnDecode { index: "\"price\"".into(), source: "not a number".into() };
+    let err = handle_db_error(decode, "ProductsStore.list");
+    assert_eq!(err.err_type, ErrorType::JsonUnmarshal);
+    assert_eq!(err.column.as_deref(), Some("price"));
+    assert_eq!(err.msg, "failed to decode the column price");
+    assert!(!err.temp);
+  }
 }
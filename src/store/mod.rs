@@ -1,2 +1,4 @@
+pub mod constraints;
 pub mod errors;
-
+pub mod pagination;
+pub mod transaction;
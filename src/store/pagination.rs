@@ -0,0 +1,525 @@
+use std::fmt;
+
+use base64::{Engine as _, engine::general_purpose::URL_SAFE_NO_PAD};
+use hmac::{Hmac, Mac};
+use megacommerce_proto::{PaginationRequest, PaginationResponse};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::Sha256;
+use sqlx::{Postgres, QueryBuilder};
+use thiserror::Error as ThisError;
+
+use crate::models::errors::ErrorType;
+
+use super::errors::{DBError, MSG_ID_DB_INVALID};
+
+pub const DEFAULT_PAGE_SIZE: u32 = 20;
+pub const MAX_PAGE_SIZE: u32 = 100;
+
+// the length of the truncated HMAC-SHA256 tag of a cursor
+const TAG_LEN: usize = 16;
+
+#[derive(Debug, ThisError, PartialEq, Eq)]
+pub enum CursorError {
+  #[error("the page token is not valid base64")]
+  Encoding,
+  #[error("the page token signature doesn't match")]
+  Signature,
+  #[error("the page token payload is invalid")]
+  Payload,
+  #[error("the page token has {got} sort keys, expected {expected}")]
+  KeysCount { expected: usize, got: usize },
+  #[error("the secret of the page tokens is empty")]
+  EmptySecret,
+  #[error("the keyset has no sort keys")]
+  NoSortKeys,
+}
+
+/// The position of a page: the sort keys of the last row of the previous page, or of the
+/// first row of the page after when paging backwards.
+///
+/// Clients get it as an opaque token signed with the service's secret, so they can't forge
+/// positions or inject values in the queries. The signature also covers a `context`, the
+/// sort keys of the list, so a token of a list isn't accepted by another.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Cursor {
+  #[serde(rename = "k")]
+  pub keys: Vec<Value>,
+  /// The rows before `keys` rather than after
+  #[serde(rename = "b", default, skip_serializing_if = "std::ops::Not::not")]
+  pub backward: bool,
+}
+
+impl Cursor {
+  /// The rows after `keys`
+  pub fn after(keys: Vec<Value>) -> Self {
+    Self { keys, backward: false }
+  }
+
+  /// The rows before `keys`
+  pub fn before(keys: Vec<Value>) -> Self {
+    Self { keys, backward: true }
+  }
+
+  /// `<base64 payload>.<base64 tag>`, URL safe
+  pub fn encode(&self, secret: &[u8], context: &[u8]) -> String {
+    let payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(self).unwrap_or_default());
+    let tag = new_mac(secret, context, payload.as_bytes()).finalize().into_bytes();
+    format!("{payload}.{}", URL_SAFE_NO_PAD.encode(&tag[..TAG_LEN]))
+  }
+
+  /// Decodes a token made by `encode` with the same `secret` and `context`, an empty
+  /// secret is rejected
+  pub fn decode(token: &str, secret: &[u8], context: &[u8]) -> Result<Self, CursorError> {
+    if secret.is_empty() {
+      return Err(CursorError::EmptySecret);
+    }
+    let (payload, tag) = token.split_once('.').ok_or(CursorError::Encoding)?;
+    let tag = URL_SAFE_NO_PAD.decode(tag).map_err(|_| CursorError::Encoding)?;
+    if tag.len() != TAG_LEN {
+      return Err(CursorError::Signature);
+    }
+    let mac = new_mac(secret, context, payload.as_bytes());
+    mac.verify_truncated_left(&tag).map_err(|_| CursorError::Signature)?;
+
+    let payload = URL_SAFE_NO_PAD.decode(payload).map_err(|_| CursorError::Encoding)?;
+    serde_json::from_slice(&payload).map_err(|_| CursorError::Payload)
+  }
+}
+
+fn new_mac(secret: &[u8], context: &[u8], payload: &[u8]) -> Hmac<Sha256> {
+  // HMAC takes keys of any length
+  let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("hmac key");
+  // length prefixed, so no context and payload sign the same bytes as another pair
+  mac.update(&(context.len() as u64).to_be_bytes());
+  mac.update(context);
+  mac.update(payload);
+  mac
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Direction {
+  #[default]
+  Asc,
+  Desc,
+}
+
+impl Direction {
+  pub fn as_sql(&self) -> &'static str {
+    match self {
+      Direction::Asc => "ASC",
+      Direction::Desc => "DESC",
+    }
+  }
+
+  fn reverse(self) -> Self {
+    match self {
+      Direction::Asc => Direction::Desc,
+      Direction::Desc => Direction::Asc,
+    }
+  }
+}
+
+impl fmt::Display for Direction {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", self.as_sql())
+  }
+}
+
+/// A column the rows are sorted by. The columns must not be null, and the last one must
+/// be unique (usually the primary key) so the positions are unambiguous.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SortKey {
+  /// The SQL expression of the column, e.g. `p.created_at`
+  pub column: String,
+  pub direction: Direction,
+  /// The type the cursor values are cast to, e.g. `timestamptz` for the timestamps
+  /// stored in the cursor as strings
+  pub cast: Option<String>,
+}
+
+impl SortKey {
+  pub fn asc(column: &str) -> Self {
+    Self { column: column.into(), direction: Direction::Asc, cast: None }
+  }
+
+  pub fn desc(column: &str) -> Self {
+    Self { column: column.into(), direction: Direction::Desc, cast: None }
+  }
+
+  pub fn cast(mut self, sql_type: &str) -> Self {
+    self.cast = Some(sql_type.into());
+    self
+  }
+}
+
+/// Keyset (seek) pagination: pages start after the sort keys of the last row seen rather
+/// than at an offset, so a page costs the same however deep it is.
+///
+/// ```ignore
+/// let keys = vec![SortKey::desc("created_at").cast("timestamptz"), SortKey::desc("id")];
+/// let keyset = Keyset::new(secret, keys)?.with_page_size(req.page_size);
+/// let cursor = keyset.cursor_of(&req, "ProductsStore.list")?;
+///
+/// let mut qb = QueryBuilder::new("SELECT * FROM products WHERE tenant_id = ");
+/// qb.push_bind(tenant_id);
+/// keyset.push_and(&mut qb, cursor.as_ref());
+/// keyset.push_order_by_limit(&mut qb, cursor.as_ref());
+///
+/// let rows: Vec<Product> = qb.build_query_as().fetch_all(pool).await.map_err(..)?;
+/// let page = keyset.page(rows, cursor.as_ref(), |p| {
+///   vec![json!(p.created_at.to_rfc3339()), json!(p.id)]
+/// });
+/// Ok((page.items, page.to_proto()))
+/// ```
+#[derive(Debug, Clone)]
+pub struct Keyset {
+  secret: Vec<u8>,
+  pub keys: Vec<SortKey>,
+  pub page_size: u32,
+}
+
+impl Keyset {
+  /// The tokens are signed with `secret`, which must not be empty, and `keys` must have at
+  /// least one sort key
+  pub fn new(secret: impl Into<Vec<u8>>, keys: Vec<SortKey>) -> Result<Self, CursorError> {
+    let secret = secret.into();
+    if secret.is_empty() {
+      return Err(CursorError::EmptySecret);
+    }
+    if keys.is_empty() {
+      return Err(CursorError::NoSortKeys);
+    }
+    Ok(Self { secret, keys, page_size: DEFAULT_PAGE_SIZE })
+  }
+
+  /// `DEFAULT_PAGE_SIZE` if unset or 0, and at most `MAX_PAGE_SIZE`
+  pub fn with_page_size(mut self, page_size: Option<u32>) -> Self {
+    self.page_size = match page_size {
+      None | Some(0) => DEFAULT_PAGE_SIZE,
+      Some(size) => size.min(MAX_PAGE_SIZE),
+    };
+    self
+  }
+
+  /// Decodes a page token, `None` for the first page. A token that is forged, or made for
+  /// other sort keys, is an `InvalidData` error.
+  #[allow(clippy::result_large_err)]
+  pub fn cursor(&self, token: Option<&str>, path: &str) -> Result<Option<Cursor>, DBError> {
+    let Some(token) = token.filter(|t| !t.is_empty()) else {
+      return Ok(None);
+    };
+    let cursor = Cursor::decode(token, &self.secret, &self.context()).and_then(|c| {
+      match c.keys.len() == self.keys.len() {
+        true => Ok(c),
+        false => Err(CursorError::KeysCount { expected: self.keys.len(), got: c.keys.len() }),
+      }
+    });
+    match cursor {
+      Ok(c) => Ok(Some(c)),
+      Err(err) => Err(DBError::new(
+        ErrorType::InvalidData,
+        Box::new(err),
+        MSG_ID_DB_INVALID,
+        path,
+        "invalid page token",
+      )),
+    }
+  }
+
+  /// The cursor of a request: its `page_token`, else `after`, else `before` paging backwards
+  #[allow(clippy::result_large_err)]
+  pub fn cursor_of(&self, req: &PaginationRequest, path: &str) -> Result<Option<Cursor>, DBError> {
+    if let Some(token) = token(&req.page_token).or(token(&req.after)) {
+      return self.cursor(Some(token), path);
+    }
+    // a `before` token pages backwards whichever way it was made
+    let cursor = self.cursor(token(&req.before), path)?;
+    Ok(cursor.map(|c| Cursor::before(c.keys)))
+  }
+
+  /// Pushes ` WHERE <condition>`, nothing for the first page
+  pub fn push_where(&self, qb: &mut QueryBuilder<'_, Postgres>, cursor: Option<&Cursor>) {
+    if let Some(cursor) = cursor {
+      qb.push(" WHERE ");
+      self.push_condition(qb, cursor);
+    }
+  }
+
+  /// Pushes ` AND <condition>`, for queries with a `WHERE` already
+  pub fn push_and(&self, qb: &mut QueryBuilder<'_, Postgres>, cursor: Option<&Cursor>) {
+    if let Some(cursor) = cursor {
+      qb.push(" AND ");
+      self.push_condition(qb, cursor);
+    }
+  }
+
+  /// Pushes the rows after (or before) the cursor: `(a, b) > ($1, $2)` when the keys go
+  /// the same direction, so an index on `(a, b)` is used, or else the expanded
+  /// `(a > $1 OR (a = $1 AND b < $2))`
+  pub fn push_condition(&self, qb: &mut QueryBuilder<'_, Postgres>, cursor: &Cursor) {
+    let op = |direction: Direction| match (direction, cursor.backward) {
+      (Direction::Asc, false) | (Direction::Desc, true) => " > ",
+      (Direction::Desc, false) | (Direction::Asc, true) => " < ",
+    };
+    let keys = self.keys.iter().zip(&cursor.keys);
+
+    if self.keys.iter().all(|k| k.direction == self.keys[0].direction) {
+      qb.push("(");
+      for (i, key) in self.keys.iter().enumerate() {
+        qb.push(if i > 0 { ", " } else { "" }).push(&key.column);
+      }
+      qb.push(")").push(op(self.keys[0].direction)).push("(");
+      for (i, (key, value)) in keys.enumerate() {
+        qb.push(if i > 0 { ", " } else { "" });
+        push_value(qb, key, value);
+      }
+      qb.push(")");
+      return;
+    }
+
+    let keys: Vec<_> = keys.collect();
+    qb.push("(");
+    for i in 0..keys.len() {
+      qb.push(if i > 0 { " OR " } else { "" }).push("(");
+      for (key, value) in &keys[..i] {
+        qb.push(&key.column).push(" = ");
+        push_value(qb, key, value);
+        qb.push(" AND ");
+      }
+      let (key, value) = keys[i];
+      qb.push(&key.column).push(op(key.direction));
+      push_value(qb, key, value);
+      qb.push(")");
+    }
+    qb.push(")");
+  }
+
+  /// Pushes ` ORDER BY a ASC, b ASC LIMIT <page size + 1>`, reversed when paging backwards.
+  /// The extra row tells `page` if there are more.
+  pub fn push_order_by_limit(&self, qb: &mut QueryBuilder<'_, Postgres>, cursor: Option<&Cursor>) {
+    let backward = cursor.is_some_and(|c| c.backward);
+    qb.push(" ORDER BY ");
+    for (i, key) in self.keys.iter().enumerate() {
+      let direction = if backward { key.direction.reverse() } else { key.direction };
+      qb.push(if i > 0 { ", " } else { "" }).push(&key.column).push(" ").push(direction);
+    }
+    qb.push(" LIMIT ").push_bind(i64::from(self.page_size) + 1);
+  }
+
+  /// The page of the `rows` fetched with `push_order_by_limit`, `keys_of` returns the
+  /// values of the sort keys of a row, in the same order as `keys`
+  pub fn page<T>(
+    &self,
+    mut rows: Vec<T>,
+    cursor: Option<&Cursor>,
+    keys_of: impl Fn(&T) -> Vec<Value>,
+  ) -> Page<T> {
+    let extra = rows.len() > self.page_size as usize;
+    rows.truncate(self.page_size as usize);
+    let backward = cursor.is_some_and(|c| c.backward);
+    if backward {
+      rows.reverse();
+    }
+
+    // going backwards, the page we came from is next
+    let (has_more, has_previous) = match backward {
+      false => (extra, cursor.is_some()),
+      true => (true, extra),
+    };
+    let context = self.context();
+    let encode = |cursor: Cursor| cursor.encode(&self.secret, &context);
+    let next_cursor =
+      rows.last().filter(|_| has_more).map(|row| encode(Cursor::after(keys_of(row))));
+    let previous_cursor =
+      rows.first().filter(|_| has_previous).map(|row| encode(Cursor::before(keys_of(row))));
+
+    Page { items: rows, next_cursor, previous_cursor, has_more, has_previous }
+  }
+
+  /// The sort keys the tokens are signed with, e.g. `created_at DESC ::timestamptz,id DESC ::`
+  fn context(&self) -> Vec<u8> {
+    let keys: Vec<_> = self
+      .keys
+      .iter()
+      .map(|k| format!("{} {} ::{}", k.column, k.direction, k.cast.as_deref().unwrap_or_default()))
+      .collect();
+    keys.join(",").into_bytes()
+  }
+}
+
+fn token(t: &Option<String>) -> Option<&str> {
+  t.as_deref().filter(|t| !t.is_empty())
+}
+
+fn push_value(qb: &mut QueryBuilder<'_, Postgres>, key: &SortKey, value: &Value) {
+  match value {
+    Value::Null => qb.push_bind(None::<String>),
+    Value::Bool(b) => qb.push_bind(*b),
+    Value::Number(n) => match n.as_i64() {
+      Some(n) => qb.push_bind(n),
+      None => qb.push_bind(n.as_f64()),
+    },
+    Value::String(s) => qb.push_bind(s.clone()),
+    other => qb.push_bind(other.to_string()),
+  };
+  if let Some(cast) = &key.cast {
+    qb.push("::").push(cast);
+  }
+}
+
+/// A page of a keyset paginated list
+#[derive(Debug, Clone, PartialEq)]
+pub struct Page<T> {
+  pub items: Vec<T>,
+  /// The token of the next page, if `has_more`
+  pub next_cursor: Option<String>,
+  /// The token of the previous page, if `has_previous`
+  pub previous_cursor: Option<String>,
+  pub has_more: bool,
+  pub has_previous: bool,
+}
+
+impl<T> Page<T> {
+  pub fn map<U>(self, f: impl FnMut(T) -> U) -> Page<U> {
+    Page {
+      items: self.items.into_iter().map(f).collect(),
+      next_cursor: self.next_cursor,
+      previous_cursor: self.previous_cursor,
+      has_more: self.has_more,
+      has_previous: self.has_previous,
+    }
+  }
+
+  pub fn to_proto(&self) -> PaginationResponse {
+    PaginationResponse {
+      next_page_token: self.next_cursor.clone(),
+      previous_page_token: self.previous_cursor.clone(),
+      has_next: Some(self.has_more),
+      has_previous: Some(self.has_previous),
+    }
+  }
+}
+
+impl<T> From<&Page<T>> for PaginationResponse {
+  fn from(page: &Page<T>) -> Self {
+    page.to_proto()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde_json::json;
+
+  const SECRET: &[u8] = b"secret";
+
+  fn new_keyset(keys: Vec<SortKey>) -> Keyset {
+    Keyset::new(SECRET, keys).unwrap().with_page_size(Some(2))
+  }
+
+  #[test]
+  fn test_cursor() {
+    let cursor = Cursor::after(vec![json!("2025-01-02T00:00:00Z"), json!(42)]);
+    let token = cursor.encode(SECRET, b"id ASC");
+    assert!(!token.contains(['+', '/', '=']));
+    assert_eq!(Cursor::decode(&token, SECRET, b"id ASC").unwrap(), cursor);
+    assert_eq!(Cursor::decode(&token, b"other", b"id ASC").unwrap_err(), CursorError::Signature);
+    assert_eq!(Cursor::decode(&token, SECRET, b"id DESC").unwrap_err(), CursorError::Signature);
+    assert_eq!(Cursor::decode(&token, b"", b"id ASC").unwrap_err(), CursorError::EmptySecret);
+
+    // another position with the signature of the first
+    let (_, tag) = token.split_once('.').unwrap();
+    let forged = URL_SAFE_NO_PAD.encode(br#"{"k":["2030-01-01T00:00:00Z",1]}"#);
+    let forged = format!("{forged}.{tag}");
+    assert_eq!(Cursor::decode(&forged, SECRET, b"id ASC").unwrap_err(), CursorError::Signature);
+    assert_eq!(Cursor::decode("nope", SECRET, b"id ASC").unwrap_err(), CursorError::Encoding);
+    // a truncated tag is not accepted either
+    let (payload, tag) = token.split_once('.').unwrap();
+    let short = URL_SAFE_NO_PAD.encode(&URL_SAFE_NO_PAD.decode(tag).unwrap()[..8]);
+    let short = format!("{payload}.{short}");
+    assert_eq!(Cursor::decode(&short, SECRET, b"id ASC").unwrap_err(), CursorError::Signature);
+
+    let keyset = new_keyset(vec![SortKey::asc("id")]);
+    let err = keyset.cursor(Some(&token), "ProductsStore.list").unwrap_err();
+    assert_eq!(err.err_type, ErrorType::InvalidData);
+    assert!(keyset.cursor(Some(""), "ProductsStore.list").unwrap().is_none());
+
+    // a token of a list isn't accepted by another with the same secret and keys count
+    let prices = new_keyset(vec![SortKey::desc("price"), SortKey::asc("id")]);
+    let dates = new_keyset(vec![SortKey::desc("created_at"), SortKey::asc("id")]);
+    let page = prices.page(vec![1, 2, 3], None, |id: &i64| vec![json!(9.5), json!(id)]);
+    let token = page.next_cursor.unwrap();
+    assert!(prices.cursor(Some(&token), "ProductsStore.list").unwrap().is_some());
+    let err = dates.cursor(Some(&token), "ProductsStore.list").unwrap_err();
+    assert_eq!(err.err_type, ErrorType::InvalidData);
+    let ascending = new_keyset(vec![SortKey::asc("price"), SortKey::asc("id")]);
+    assert!(ascending.cursor(Some(&token), "ProductsStore.list").is_err());
+
+    assert_eq!(Keyset::new("", vec![]).unwrap_err(), CursorError::EmptySecret);
+    assert_eq!(Keyset::new(SECRET, vec![]).unwrap_err(), CursorError::NoSortKeys);
+  }
+
+  #[test]
+  fn test_sql() {
+    let keyset =
+      new_keyset(vec![SortKey::desc("created_at").cast("timestamptz"), SortKey::desc("id")]);
+    let cursor = Cursor::after(vec![json!("2025-01-02T00:00:00Z"), json!(42)]);
+
+    let mut qb = QueryBuilder::new("SELECT * FROM products");
+    keyset.push_where(&mut qb, Some(&cursor));
+    keyset.push_order_by_limit(&mut qb, Some(&cursor));
+    assert_eq!(
+      qb.sql(),
+      "SELECT * FROM products WHERE (created_at, id) < ($1::timestamptz, $2) \
+       ORDER BY created_at DESC, id DESC LIMIT $3"
+    );
+
+    let mut qb = QueryBuilder::new("SELECT * FROM products WHERE tenant_id = 1");
+    keyset.push_and(&mut qb, Some(&Cursor { backward: true, ..cursor.clone() }));
+    keyset.push_order_by_limit(&mut qb, Some(&Cursor { backward: true, ..cursor }));
+    assert_eq!(
+      qb.sql(),
+      "SELECT * FROM products WHERE tenant_id = 1 AND (created_at, id) > ($1::timestamptz, $2) \
+       ORDER BY created_at ASC, id ASC LIMIT $3"
+    );
+
+    let mut qb = QueryBuilder::new("SELECT * FROM products");
+    keyset.push_where(&mut qb, None);
+    keyset.push_order_by_limit(&mut qb, None);
+    assert_eq!(qb.sql(), "SELECT * FROM products ORDER BY created_at DESC, id DESC LIMIT $1");
+
+    // mixed directions
+    let keyset = new_keyset(vec![SortKey::asc("price"), SortKey::desc("id")]);
+    let mut qb = QueryBuilder::new("SELECT * FROM products");
+    keyset.push_where(&mut qb, Some(&Cursor::after(vec![json!(9.5), json!(7)])));
+    assert_eq!(qb.sql(), "SELECT * FROM products WHERE ((price > $1) OR (price = $2 AND id < $3))");
+  }
+
+  #[test]
+  fn test_page() {
+    let keyset = new_keyset(vec![SortKey::asc("id")]);
+    let keys_of = |id: &i64| vec![json!(id)];
+
+    let page = keyset.page(vec![1, 2, 3], None, keys_of);
+    assert_eq!(page.items, vec![1, 2]);
+    assert!(page.has_more && !page.has_previous);
+    let next = keyset.cursor(page.next_cursor.as_deref(), "").unwrap().unwrap();
+    assert_eq!(next, Cursor::after(vec![json!(2)]));
+
+    let page = keyset.page(vec![3], Some(&next), keys_of);
+    assert!(!page.has_more && page.has_previous);
+    assert_eq!(page.next_cursor, None);
+    let previous = keyset.cursor(page.previous_cursor.as_deref(), "").unwrap().unwrap();
+    assert_eq!(previous, Cursor::before(vec![json!(3)]));
+
+    // fetched in reverse order, the extra row means there is a page before
+    let page = keyset.page(vec![2, 1, 0], Some(&previous), keys_of);
+    assert_eq!(page.items, vec![1, 2]);
+    assert!(page.has_more && page.has_previous);
+
+    let proto = page.map(|id| id.to_string()).to_proto();
+    assert_eq!(proto.has_next, Some(true));
+    assert!(proto.next_page_token.is_some() && proto.previous_page_token.is_some());
+  }
+}
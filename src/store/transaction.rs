@@ -0,0 +1,311 @@
+use std::fmt;
+
+use futures_core::future::BoxFuture;
+use sqlx::{PgPool, Postgres, Transaction};
+
+use crate::models::errors::ErrorType;
+use crate::utils::retry::{RetryCancelled, RetryPolicy, Retryable, retry};
+
+use super::errors::{DBError, handle_db_error};
+
+const PATH: &str = "store.with_transaction";
+
+/// The isolation level of a transaction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IsolationLevel {
+  /// The Postgres default
+  #[default]
+  ReadCommitted,
+  RepeatableRead,
+  Serializable,
+}
+
+impl IsolationLevel {
+  pub fn as_sql(&self) -> &'static str {
+    match self {
+      IsolationLevel::ReadCommitted => "READ COMMITTED",
+      IsolationLevel::RepeatableRead => "REPEATABLE READ",
+      IsolationLevel::Serializable => "SERIALIZABLE",
+    }
+  }
+}
+
+impl fmt::Display for IsolationLevel {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", self.as_sql())
+  }
+}
+
+// Only the transactions aborted by a concurrent one are retried, unlike `retry` alone
+struct TxError(DBError);
+
+impl Retryable for TxError {
+  fn is_retryable(&self) -> bool {
+    matches!(self.0.err_type, ErrorType::SerializationFailure | ErrorType::DeadlockDetected)
+  }
+}
+
+impl From<RetryCancelled> for TxError {
+  fn from(e: RetryCancelled) -> Self {
+    Self(e.into())
+  }
+}
+
+impl fmt::Display for TxError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    self.0.fmt(f)
+  }
+}
+
+/// Runs `f` in a transaction with the `isolation` level, committing if it succeeds and
+/// rolling back if it fails. A transaction aborted by a serialization failure (40001) or a
+/// deadlock (40P01) is run again from the start, up to 5 times with backoff; see
+/// `with_transaction_retry` for another policy.
+///
+/// ```ignore
+/// let id = with_transaction(&pool, IsolationLevel::Serializable, |tx| {
+///   Box::pin(async move {
+///     sqlx::query_scalar("INSERT INTO orders (total) VALUES ($1) RETURNING id")
+///       .bind(total)
+///       .fetch_one(&mut **tx)
+///       .await
+///       .map_err(|e| handle_db_error(e, "OrdersStore.create"))
+///   })
+/// })
+/// .await?;
+/// ```
+pub async fn with_transaction<T, F>(
+  pool: &PgPool,
+  isolation: IsolationLevel,
+  f: F,
+) -> Result<T, DBError>
+where
+  F: for<'c> FnMut(&'c mut Transaction<'static, Postgres>) -> BoxFuture<'c, Result<T, DBError>>,
+{
+  let policy = RetryPolicy { max_attempts: Some(5), ..Default::default() };
+  with_transaction_retry(pool, isolation, &policy, f).await
+}
+
+/// Same as `with_transaction`, retrying as set by `policy`
+pub async fn with_transaction_retry<T, F>(
+  pool: &PgPool,
+  isolation: IsolationLevel,
+  policy: &RetryPolicy,
+  f: F,
+) -> Result<T, DBError>
+where
+  F: for<'c> FnMut(&'c mut Transaction<'static, Postgres>) -> BoxFuture<'c, Result<T, DBError>>,
+{
+  with_transaction_on(pool, isolation, policy, f).await
+}
+
+/// Begins, commits and rolls back the transactions of `with_transaction_on`
+pub trait TransactionExecutor {
+  type Tx: Send;
+
+  /// Begins a transaction with the `isolation` level
+  fn begin_with(&self, isolation: IsolationLevel) -> BoxFuture<'_, Result<Self::Tx, DBError>>;
+  fn commit(&self, tx: Self::Tx) -> BoxFuture<'_, Result<(), DBError>>;
+  fn rollback(&self, tx: Self::Tx) -> BoxFuture<'_, Result<(), DBError>>;
+}
+
+impl TransactionExecutor for PgPool {
+  type Tx = Transaction<'static, Postgres>;
+
+  fn begin_with(&self, isolation: IsolationLevel) -> BoxFuture<'_, Result<Self::Tx, DBError>> {
+    Box::pin(async move {
+      let mut tx = self.begin().await.map_err(|e| handle_db_error(e, PATH))?;
+      let set_isolation = format!("SET TRANSACTION ISOLATION LEVEL {}", isolation.as_sql());
+      sqlx::query(&set_isolation).execute(&mut *tx).await.map_err(|e| handle_db_error(e, PATH))?;
+      Ok(tx)
+    })
+  }
+
+  fn commit(&self, tx: Self::Tx) -> BoxFuture<'_, Result<(), DBError>> {
+    Box::pin(async move { tx.commit().await.map_err(|e| handle_db_error(e, PATH)) })
+  }
+
+  fn rollback(&self, tx: Self::Tx) -> BoxFuture<'_, Result<(), DBError>> {
+    Box::pin(async move { tx.rollback().await.map_err(|e| handle_db_error(e, PATH)) })
+  }
+}
+
+/// Same as `with_transaction_retry`, with the transactions of `executor`
+pub async fn with_transaction_on<X, T, F>(
+  executor: &X,
+  isolation: IsolationLevel,
+  policy: &RetryPolicy,
+  f: F,
+) -> Result<T, DBError>
+where
+  X: TransactionExecutor,
+  F: for<'c> FnMut(&'c mut X::Tx) -> BoxFuture<'c, Result<T, DBError>>,
+{
+  // the attempts run one after the other, the lock is only there to reborrow `f`
+  let f = tokio::sync::Mutex::new(f);
+  retry(PATH, policy, || async {
+    let mut f = f.lock().await;
+    let mut tx = executor.begin_with(isolation).await.map_err(TxError)?;
+
+    match f(&mut tx).await {
+      Ok(value) => {
+        executor.commit(tx).await.map_err(TxError)?;
+        Ok(value)
+      }
+      Err(err) => {
+        // the error of `f` matters more than a failed rollback
+        if let Err(rollback_err) = executor.rollback(tx).await {
+          tracing::warn!(error = %rollback_err, "failed to roll back a transaction");
+        }
+        Err(TxError(err))
+      }
+    }
+  })
+  .await
+  .map_err(|TxError(err)| err)
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::Mutex;
+  use std::time::Duration;
+
+  use super::*;
+
+  #[derive(Debug, Clone, PartialEq)]
+  enum Event {
+    Begin(IsolationLevel),
+    Commit,
+    Rollback,
+  }
+
+  /// Records the transactions, the commits fail with the errors of `commit_errors`
+  #[derive(Default)]
+  struct MockExecutor {
+    events: Mutex<Vec<Event>>,
+    commit_errors: Mutex<Vec<ErrorType>>,
+  }
+
+  impl MockExecutor {
+    fn events(&self) -> Vec<Event> {
+      self.events.lock().unwrap().clone()
+    }
+  }
+
+  impl TransactionExecutor for MockExecutor {
+    type Tx = Vec<&'static str>;
+
+    fn begin_with(&self, isolation: IsolationLevel) -> BoxFuture<'_, Result<Self::Tx, DBError>> {
+      self.events.lock().unwrap().push(Event::Begin(isolation));
+      Box::pin(async { Ok(vec![]) })
+    }
+
+    fn commit(&self, _tx: Self::Tx) -> BoxFuture<'_, Result<(), DBError>> {
+      self.events.lock().unwrap().push(Event::Commit);
+      let err = self.commit_errors.lock().unwrap().pop();
+      Box::pin(async move {
+        match err {
+          Some(err_type) => Err(db_error(err_type)),
+          None => Ok(()),
+        }
+      })
+    }
+
+    fn rollback(&self, _tx: Self::Tx) -> BoxFuture<'_, Result<(), DBError>> {
+      self.events.lock().unwrap().push(Event::Rollback);
+      Box::pin(async { Ok(()) })
+    }
+  }
+
+  fn db_error(err_type: ErrorType) -> DBError {
+    DBError::new(err_type, "failed".into(), "failed", "OrdersStore.create", "")
+  }
+
+  fn policy() -> RetryPolicy {
+    let delay = Duration::from_millis(1);
+    RetryPolicy {
+      initial_delay: delay,
+      max_delay: delay,
+      max_attempts: Some(3),
+      ..Default::default()
+    }
+  }
+
+  #[tokio::test]
+  async fn test_commit() {
+    let executor = MockExecutor::default();
+    let value = with_transaction_on(&executor, IsolationLevel::Serializable, &policy(), |tx| {
+      Box::pin(async move {
+        tx.push("insert");
+        Ok(tx.len())
+      })
+    })
+    .await
+    .unwrap();
+    assert_eq!(value, 1);
+    assert_eq!(executor.events(), vec![Event::Begin(IsolationLevel::Serializable), Event::Commit]);
+  }
+
+  #[tokio::test]
+  async fn test_rollback() {
+    let executor = MockExecutor::default();
+    let err = with_transaction_on(&executor, IsolationLevel::ReadCommitted, &policy(), |_| {
+      Box::pin(async { Err::<(), _>(db_error(ErrorType::UniqueViolation)) })
+    })
+    .await
+    .unwrap_err();
+    assert_eq!(err.err_type, ErrorType::UniqueViolation);
+    // not retried, even if transient
+    assert_eq!(
+      executor.events(),
+      vec![Event::Begin(IsolationLevel::ReadCommitted), Event::Rollback]
+    );
+
+    let executor = MockExecutor::default();
+    with_transaction_on(&executor, IsolationLevel::ReadCommitted, &policy(), |_| {
+      Box::pin(async { Err::<(), _>(db_error(ErrorType::TimedOut)) })
+    })
+    .await
+    .unwrap_err();
+    assert_eq!(executor.events().len(), 2);
+  }
+
+  #[tokio::test]
+  async fn test_retry() {
+    let level = IsolationLevel::RepeatableRead;
+    let executor = MockExecutor::default();
+    let mut attempt = 0;
+    with_transaction_on(&executor, level, &policy(), |_| {
+      attempt += 1;
+      let first = attempt == 1;
+      Box::pin(async move {
+        match first {
+          true => Err(db_error(ErrorType::SerializationFailure)),
+          false => Ok(()),
+        }
+      })
+    })
+    .await
+    .unwrap();
+    let expected = [Event::Begin(level), Event::Rollback, Event::Begin(level), Event::Commit];
+    assert_eq!(executor.events(), expected);
+
+    // a commit aborted by a concurrent transaction is retried too
+    let executor = MockExecutor::default();
+    executor.commit_errors.lock().unwrap().push(ErrorType::SerializationFailure);
+    with_transaction_on(&executor, level, &policy(), |_| Box::pin(async { Ok(()) })).await.unwrap();
+    let expected = [Event::Begin(level), Event::Commit, Event::Begin(level), Event::Commit];
+    assert_eq!(executor.events(), expected);
+
+    // up to `max_attempts`
+    let executor = MockExecutor::default();
+    let err = with_transaction_on(&executor, level, &policy(), |_| {
+      Box::pin(async { Err::<(), _>(db_error(ErrorType::DeadlockDetected)) })
+    })
+    .await
+    .unwrap_err();
+    assert_eq!(err.err_type, ErrorType::DeadlockDetected);
+    let begins = executor.events().iter().filter(|e| matches!(e, Event::Begin(_))).count();
+    assert_eq!(begins, 3);
+  }
+}
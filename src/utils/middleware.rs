@@ -5,8 +5,10 @@ use tonic::{Request, Status};
 use crate::models::{
   context::{Context, Session},
   network::Header,
+  translate::default_translator,
 };
 
+#[allow(clippy::result_large_err)]
 pub fn middleware_context(mut req: Request<()>) -> Result<Request<()>, Status> {
   let m = req.metadata_mut();
 
@@ -51,7 +53,7 @@ pub fn middleware_context(mut req: Request<()>) -> Result<Request<()>, Status> {
       props: get_props(Header::XProps.as_str()),
     };
 
-    Context::new(
+    let context = Context::new(
       session,
       get_string(Header::XRequestID.as_str()),
       get_string(Header::XIPAddress.as_str()),
@@ -60,7 +62,15 @@ pub fn middleware_context(mut req: Request<()>) -> Result<Request<()>, Status> {
       get_string(Header::UserAgent.as_str()),
       get_string(Header::AcceptLanguage.as_str()),
       get_string(Header::XTimezone.as_str()),
-    )
+    );
+
+    match default_translator() {
+      Ok(translator) => {
+        let language = translator.negotiate(context.accept_language());
+        context.with_language(language)
+      }
+      Err(_) => context,
+    }
   };
 
   req.extensions_mut().insert(Arc::new(context));
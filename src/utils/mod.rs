@@ -1,3 +1,4 @@
 pub mod grpc;
 pub mod middleware;
+pub mod retry;
 pub mod time;
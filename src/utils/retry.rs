@@ -0,0 +1,271 @@
+use std::{error::Error, fmt, future::Future, time::Duration};
+
+use rand::Rng;
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+use tracing::{Instrument, field, info_span, warn};
+
+use crate::models::errors::{ErrorType, InternalError};
+
+/// An error that may go away if the operation is retried
+pub trait Retryable {
+  fn is_retryable(&self) -> bool;
+}
+
+// `temp` alone, like `DBError`: the caller knows better than the error type whether an
+// error is worth retrying
+impl Retryable for InternalError {
+  fn is_retryable(&self) -> bool {
+    self.temp
+  }
+}
+
+/// Returned through `From` when the `CancellationToken` of a `RetryPolicy` is cancelled
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryCancelled {
+  pub path: String,
+  pub attempts: u32,
+}
+
+impl fmt::Display for RetryCancelled {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}: cancelled after {} attempts", self.path, self.attempts)
+  }
+}
+
+impl Error for RetryCancelled {}
+
+impl From<RetryCancelled> for InternalError {
+  fn from(e: RetryCancelled) -> Self {
+    let msg = e.to_string();
+    InternalError::new(e.path.clone(), Box::new(e), ErrorType::TaskFailed, false, msg)
+  }
+}
+
+/// How `retry` waits between attempts: exponential backoff from `initial_delay` up to
+/// `max_delay`, each delay randomized by ± `jitter` (0 to 1) so that clients failing
+/// together don't retry together.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+  pub initial_delay: Duration,
+  pub max_delay: Duration,
+  pub multiplier: f64,
+  pub jitter: f64,
+  /// No attempt is started after this, counted from the first one
+  pub max_elapsed: Duration,
+  /// Unlimited if `None`
+  pub max_attempts: Option<u32>,
+  /// Stops the attempt in progress and the waiting between attempts
+  pub cancel: Option<CancellationToken>,
+}
+
+impl Default for RetryPolicy {
+  fn default() -> Self {
+    Self {
+      initial_delay: Duration::from_millis(100),
+      max_delay: Duration::from_secs(5),
+      multiplier: 2.0,
+      jitter: 0.5,
+      max_elapsed: Duration::from_secs(30),
+      max_attempts: None,
+      cancel: None,
+    }
+  }
+}
+
+impl RetryPolicy {
+  pub fn with_cancel(mut self, cancel: CancellationToken) -> Self {
+    self.cancel = Some(cancel);
+    self
+  }
+
+  /// The delay before the attempt following `attempt` (1 for the first), without jitter
+  pub fn base_delay(&self, attempt: u32) -> Duration {
+    let factor = self.multiplier.powi(attempt.saturating_sub(1).min(1024) as i32);
+    let secs = self.initial_delay.as_secs_f64() * factor;
+    Duration::from_secs_f64(secs.min(self.max_delay.as_secs_f64()))
+  }
+
+  fn delay(&self, attempt: u32) -> Duration {
+    let base = self.base_delay(attempt);
+    let jitter = self.jitter.clamp(0.0, 1.0);
+    if jitter == 0.0 {
+      return base;
+    }
+    let factor = rand::rng().random_range(1.0 - jitter..=1.0 + jitter);
+    base.mul_f64(factor).min(self.max_delay)
+  }
+}
+
+/// Runs `op` until it succeeds or fails with an error that isn't `Retryable`, waiting
+/// between the attempts as set by `policy`. When the attempts or the time run out, the
+/// last error is returned.
+///
+/// The attempts are recorded in a `retry` span with the `path`, and a warning is logged
+/// for every retried error.
+///
+/// ```ignore
+/// let product = retry("ProductsStore.get", &RetryPolicy::default(), || async {
+///   sqlx::query_as(..).fetch_one(&pool).await.map_err(|e| handle_db_error(e, path))
+/// })
+/// .await?;
+/// ```
+pub async fn retry<T, E, F, Fut>(path: &str, policy: &RetryPolicy, mut op: F) -> Result<T, E>
+where
+  F: FnMut() -> Fut,
+  Fut: Future<Output = Result<T, E>>,
+  E: Retryable + From<RetryCancelled> + fmt::Display,
+{
+  let span = info_span!("retry", path, attempts = field::Empty);
+  async move {
+    let start = Instant::now();
+    let cancelled = |attempts| E::from(RetryCancelled { path: path.to_string(), attempts });
+    let mut attempt = 0;
+
+    loop {
+      attempt += 1;
+      tracing::Span::current().record("attempts", attempt);
+
+      let fut = op().instrument(info_span!("attempt", attempt));
+      let result = match &policy.cancel {
+        Some(cancel) => tokio::select! {
+          biased;
+          _ = cancel.cancelled() => return Err(cancelled(attempt)),
+          result = fut => result,
+        },
+        None => fut.await,
+      };
+
+      let err = match result {
+        Ok(value) => return Ok(value),
+        Err(err) if !err.is_retryable() => return Err(err),
+        Err(err) => err,
+      };
+
+      let delay = policy.delay(attempt);
+      let out_of_attempts = policy.max_attempts.is_some_and(|max| attempt >= max);
+      if out_of_attempts || start.elapsed() + delay > policy.max_elapsed {
+        return Err(err);
+      }
+      warn!(attempt, delay_ms = delay.as_millis() as u64, error = %err, "retrying");
+
+      match &policy.cancel {
+        Some(cancel) => tokio::select! {
+          biased;
+          _ = cancel.cancelled() => return Err(cancelled(attempt)),
+          _ = tokio::time::sleep(delay) => {}
+        },
+        None => tokio::time::sleep(delay).await,
+      }
+    }
+  }
+  .instrument(span)
+  .await
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::atomic::{AtomicU32, Ordering};
+
+  use super::*;
+
+  fn policy() -> RetryPolicy {
+    RetryPolicy {
+      initial_delay: Duration::from_millis(1),
+      max_delay: Duration::from_millis(4),
+      ..Default::default()
+    }
+  }
+
+  fn err(err_type: ErrorType, temp: bool) -> InternalError {
+    InternalError::new("test".into(), "failed".into(), err_type, temp, "failed".into())
+  }
+
+  #[test]
+  fn test_base_delay() {
+    let policy = RetryPolicy::default();
+    assert_eq!(policy.base_delay(1), Duration::from_millis(100));
+    assert_eq!(policy.base_delay(3), Duration::from_millis(400));
+    assert_eq!(policy.base_delay(100), Duration::from_secs(5));
+    for attempt in 1..10 {
+      let delay = policy.delay(attempt);
+      assert!(delay >= policy.base_delay(attempt).mul_f64(0.5) && delay <= policy.max_delay);
+    }
+  }
+
+  #[tokio::test]
+  async fn test_retry() {
+    let calls = AtomicU32::new(0);
+    let result = retry("test", &policy(), || async {
+      match calls.fetch_add(1, Ordering::SeqCst) {
+        0 => Err(err(ErrorType::Connection, true)),
+        1 => Err(err(ErrorType::Internal, true)),
+        _ => Ok(5),
+      }
+    })
+    .await;
+    assert_eq!(result.unwrap(), 5);
+    assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+    // not retryable
+    let calls = AtomicU32::new(0);
+    let result: Result<(), _> = retry("test", &policy(), || async {
+      calls.fetch_add(1, Ordering::SeqCst);
+      Err(err(ErrorType::UniqueViolation, false))
+    })
+    .await;
+    assert_eq!(result.unwrap_err().err_type, ErrorType::UniqueViolation);
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+    // a transient type the caller marked as not temporary, e.g. a closed pool
+    let calls = AtomicU32::new(0);
+    let result: Result<(), _> = retry("test", &policy(), || async {
+      calls.fetch_add(1, Ordering::SeqCst);
+      Err(err(ErrorType::Connection, false))
+    })
+    .await;
+    assert_eq!(result.unwrap_err().err_type, ErrorType::Connection);
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+    // out of attempts
+    let calls = AtomicU32::new(0);
+    let policy = RetryPolicy { max_attempts: Some(4), ..policy() };
+    let result: Result<(), _> = retry("test", &policy, || async {
+      calls.fetch_add(1, Ordering::SeqCst);
+      Err(err(ErrorType::TimedOut, true))
+    })
+    .await;
+    assert_eq!(result.unwrap_err().err_type, ErrorType::TimedOut);
+    assert_eq!(calls.load(Ordering::SeqCst), 4);
+  }
+
+  #[tokio::test]
+  async fn test_retry_max_elapsed() {
+    let policy = RetryPolicy { max_elapsed: Duration::from_millis(20), ..policy() };
+    let start = std::time::Instant::now();
+    let result: Result<(), _> =
+      retry("test", &policy, || async { Err(err(ErrorType::Connection, true)) }).await;
+    assert_eq!(result.unwrap_err().err_type, ErrorType::Connection);
+    assert!(start.elapsed() < Duration::from_secs(1));
+  }
+
+  #[tokio::test]
+  async fn test_retry_cancel() {
+    let cancel = CancellationToken::new();
+    let policy = policy().with_cancel(cancel.clone());
+    let calls = AtomicU32::new(0);
+    let result: Result<(), InternalError> = retry("test", &policy, || async {
+      if calls.fetch_add(1, Ordering::SeqCst) == 1 {
+        cancel.cancel();
+        std::future::pending::<()>().await;
+      }
+      Err(err(ErrorType::Connection, true))
+    })
+    .await;
+
+    let err = result.unwrap_err();
+    assert_eq!(err.err_type, ErrorType::TaskFailed);
+    assert_eq!(err.msg, "test: cancelled after 2 attempts");
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+  }
+}
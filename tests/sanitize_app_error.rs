@@ -0,0 +1,132 @@
+use megacommerce_proto::{
+  AppError as AppErrorProto, ProductCreateResponse, product_create_response,
+};
+use megacommerce_shared::models::errors::SanitizeAppError;
+
+// a service's own response type with the shape of the proto responses
+mod service {
+  #[derive(Debug, Clone, PartialEq)]
+  pub enum Response {
+    Data(String),
+    Error(megacommerce_proto::AppError),
+  }
+}
+
+struct ServiceResponse {
+  response: Option<service::Response>,
+}
+
+megacommerce_shared::sanitize_app_error!(ServiceResponse, service::Response);
+
+fn app_error() -> AppErrorProto {
+  AppErrorProto {
+    id: "products.create.sku_taken".to_string(),
+    message: "The SKU is already taken".to_string(),
+    detailed_error: "Key (sku)=(A-1) already exists.".to_string(),
+    r#where: "ProductsStore.create".to_string(),
+    status_code: tonic::Code::AlreadyExists as i32,
+    ..Default::default()
+  }
+}
+
+fn assert_sanitized(err: &AppErrorProto) {
+  assert_eq!(err.id, "products.create.sku_taken");
+  assert_eq!(err.message, "The SKU is already taken");
+  assert!(err.detailed_error.is_empty() && err.r#where.is_empty());
+}
+
+#[test]
+fn test_sanitize_app_error_macro() {
+  let mut resp = ServiceResponse { response: Some(service::Response::Error(app_error())) };
+  resp.sanitize_app_error_fields();
+  let Some(service::Response::Error(err)) = &resp.response else { panic!("expected an error") };
+  assert_sanitized(err);
+
+  let mut resp = ServiceResponse { response: Some(service::Response::Data("ok".to_string())) };
+  resp.sanitize_app_error_fields();
+  assert_eq!(resp.response, Some(service::Response::Data("ok".to_string())));
+}
+
+#[test]
+fn test_sanitize_proto_response() {
+  let error = product_create_response::Response::Error(app_error());
+  let mut resp = ProductCreateResponse { response: Some(error) };
+  resp.sanitize_app_error_fields();
+  let Some(product_create_response::Response::Error(err)) = &resp.response else {
+    panic!("expected an error")
+  };
+  assert_sanitized(err);
+}
+
+// several error-bearing fields and nested responses
+#[derive(SanitizeAppError)]
+struct OrderItemResponse {
+  #[app_error(oneof(service::Response::Error))]
+  response: Option<service::Response>,
+}
+
+#[derive(SanitizeAppError)]
+struct OrderResponse {
+  #[app_error(oneof(product_create_response::Response::Error))]
+  product: Option<product_create_response::Response>,
+  #[app_error]
+  warning: Option<AppErrorProto>,
+  #[app_error]
+  errors: Vec<AppErrorProto>,
+  #[app_error]
+  items: Vec<OrderItemResponse>,
+  #[app_error]
+  parent: Option<Box<OrderResponse>>,
+  note: String,
+}
+
+#[derive(SanitizeAppError)]
+struct Wrapped(#[app_error] AppErrorProto);
+
+#[test]
+fn test_sanitize_app_error_derive() {
+  let item = |response| OrderItemResponse { response: Some(response) };
+  let mut resp = OrderResponse {
+    product: Some(product_create_response::Response::Error(app_error())),
+    warning: Some(app_error()),
+    errors: vec![app_error(), app_error()],
+    items: vec![
+      item(service::Response::Error(app_error())),
+      item(service::Response::Data("ok".to_string())),
+    ],
+    parent: Some(Box::new(OrderResponse {
+      product: None,
+      warning: Some(app_error()),
+      errors: vec![],
+      items: vec![],
+      parent: None,
+      note: String::new(),
+    })),
+    note: "Key (sku)=(A-1)".to_string(),
+  };
+  resp.sanitize_app_error_fields();
+
+  let Some(product_create_response::Response::Error(err)) = &resp.product else {
+    panic!("expected an error")
+  };
+  assert_sanitized(err);
+  assert_sanitized(resp.warning.as_ref().unwrap());
+  resp.errors.iter().for_each(assert_sanitized);
+  let Some(service::Response::Error(err)) = &resp.items[0].response else {
+    panic!("expected an error")
+  };
+  assert_sanitized(err);
+  assert_eq!(resp.items[1].response, Some(service::Response::Data("ok".to_string())));
+  assert_sanitized(resp.parent.as_ref().unwrap().warning.as_ref().unwrap());
+  // unmarked fields are left alone
+  assert_eq!(resp.note, "Key (sku)=(A-1)");
+
+  let mut wrapped = Wrapped(app_error());
+  wrapped.sanitize_app_error_fields();
+  assert_sanitized(&wrapped.0);
+}
+
+#[test]
+fn test_sanitize_app_error_derive_compile_errors() {
+  trybuild::TestCases::new().compile_fail("tests/ui/sanitize_*.rs");
+}
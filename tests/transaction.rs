@@ -0,0 +1,159 @@
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+
+use megacommerce_shared::models::errors::ErrorType;
+use megacommerce_shared::store::errors::{DBError, handle_db_error};
+use megacommerce_shared::store::transaction::{IsolationLevel, with_transaction};
+use sqlx::PgPool;
+
+// A throwaway server in a temp dir, reachable on a unix socket only
+struct LocalPostgres {
+  dir: PathBuf,
+  server: Child,
+}
+
+impl Drop for LocalPostgres {
+  fn drop(&mut self) {
+    let _ = self.server.kill();
+    let _ = self.server.wait();
+    let _ = std::fs::remove_dir_all(&self.dir);
+  }
+}
+
+impl LocalPostgres {
+  fn spawn() -> Result<Self, String> {
+    let dir = std::env::temp_dir().join(format!("postgres-{}", ulid::Ulid::new()));
+    let initdb = Command::new("initdb")
+      .args(["-U", "postgres", "--auth=trust", "-D"])
+      .arg(dir.join("data"))
+      .stdout(Stdio::null())
+      .stderr(Stdio::piped())
+      .output();
+    // initdb isn't installed, or refuses to run as root
+    let initdb_err = match initdb {
+      Ok(out) if out.status.success() => None,
+      Ok(out) => Some(format!("initdb failed: {}", String::from_utf8_lossy(&out.stderr).trim())),
+      Err(e) => Some(format!("can't run initdb: {e}")),
+    };
+    if let Some(err) = initdb_err {
+      let _ = std::fs::remove_dir_all(&dir);
+      return Err(err);
+    }
+
+    let server = Command::new("postgres")
+      .arg("-D")
+      .arg(dir.join("data"))
+      .arg("-k")
+      .arg(&dir)
+      .args(["-c", "listen_addresses="])
+      .stdout(Stdio::null())
+      .stderr(Stdio::null())
+      .spawn()
+      .map_err(|e| format!("can't run postgres: {e}"))?;
+    Ok(Self { dir, server })
+  }
+
+  fn url(&self) -> String {
+    format!("postgres://postgres@localhost/postgres?host={}", self.dir.display())
+  }
+}
+
+/// Connects to `DATABASE_URL`, or else to a spawned server
+async fn pool() -> Result<(PgPool, Option<LocalPostgres>), String> {
+  let (url, local) = match std::env::var("DATABASE_URL") {
+    Ok(url) => (url, None),
+    Err(_) => {
+      let local = LocalPostgres::spawn()?;
+      (local.url(), Some(local))
+    }
+  };
+
+  let mut last_err = None;
+  for _ in 0..50 {
+    match PgPool::connect(&url).await {
+      Ok(pool) => return Ok((pool, local)),
+      Err(e) => last_err = Some(e),
+    }
+    tokio::time::sleep(Duration::from_millis(100)).await;
+  }
+  Err(format!("can't connect to {url}: {}", last_err.unwrap()))
+}
+
+async fn count(pool: &PgPool, table: &str) -> i64 {
+  sqlx::query_scalar(&format!("SELECT count(*) FROM {table}")).fetch_one(pool).await.unwrap()
+}
+
+fn db_err(path: &'static str) -> impl Fn(sqlx::Error) -> DBError {
+  move |e| handle_db_error(e, path)
+}
+
+// the logic without a server is tested in `store::transaction`
+#[tokio::test]
+#[ignore = "needs Postgres: set DATABASE_URL, or have initdb on the PATH as a non-root user"]
+async fn test_with_transaction() {
+  let (pool, _local) = pool().await.unwrap_or_else(|e| panic!("no Postgres to test with: {e}"));
+  let table = format!("tx_test_{}", ulid::Ulid::new().to_string().to_lowercase());
+  sqlx::query(&format!("CREATE TABLE {table} (id INT PRIMARY KEY)")).execute(&pool).await.unwrap();
+  let insert = format!("INSERT INTO {table} (id) VALUES ($1)");
+
+  // committed, at the requested isolation level
+  let level = with_transaction(&pool, IsolationLevel::Serializable, |tx| {
+    let insert = insert.clone();
+    Box::pin(async move {
+      sqlx::query(&insert).bind(1).execute(&mut **tx).await.map_err(db_err("insert"))?;
+      sqlx::query_scalar::<_, String>("SHOW transaction_isolation")
+        .fetch_one(&mut **tx)
+        .await
+        .map_err(db_err("show"))
+    })
+  })
+  .await
+  .unwrap();
+  assert_eq!(level, "serializable");
+  assert_eq!(count(&pool, &table).await, 1);
+
+  // rolled back
+  let err = with_transaction(&pool, IsolationLevel::ReadCommitted, |tx| {
+    let insert = insert.clone();
+    Box::pin(async move {
+      sqlx::query(&insert).bind(2).execute(&mut **tx).await.map_err(db_err("insert"))?;
+      sqlx::query(&insert).bind(1).execute(&mut **tx).await.map_err(db_err("insert"))?;
+      Ok(())
+    })
+  })
+  .await
+  .unwrap_err();
+  assert_eq!(err.err_type, ErrorType::UniqueViolation);
+  assert_eq!(count(&pool, &table).await, 1);
+
+  // retried after a serialization failure, the first attempt is rolled back
+  let attempts = AtomicU32::new(0);
+  with_transaction(&pool, IsolationLevel::Serializable, |tx| {
+    let insert = insert.clone();
+    let attempt = attempts.fetch_add(1, Ordering::SeqCst);
+    Box::pin(async move {
+      sqlx::query(&insert)
+        .bind(10 + attempt as i32)
+        .execute(&mut **tx)
+        .await
+        .map_err(db_err("insert"))?;
+      if attempt == 0 {
+        let conflict = "DO $$ BEGIN RAISE EXCEPTION 'conflict' USING ERRCODE = '40001'; END $$";
+        sqlx::query(conflict).execute(&mut **tx).await.map_err(db_err("conflict"))?;
+      }
+      Ok(())
+    })
+  })
+  .await
+  .unwrap();
+  assert_eq!(attempts.load(Ordering::SeqCst), 2);
+  let ids: Vec<i32> = sqlx::query_scalar(&format!("SELECT id FROM {table} ORDER BY id"))
+    .fetch_all(&pool)
+    .await
+    .unwrap();
+  assert_eq!(ids, vec![1, 11]);
+
+  sqlx::query(&format!("DROP TABLE {table}")).execute(&pool).await.unwrap();
+}
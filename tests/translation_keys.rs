@@ -0,0 +1,36 @@
+use megacommerce_shared::models::translate::{Translator, TypedKey, load_translations_dir};
+
+mod keys {
+  megacommerce_shared::translation_keys!("fixtures/translations/en.yaml");
+}
+
+fn translator() -> Translator {
+  let trans = load_translations_dir("tests/fixtures/translations").unwrap();
+  Translator::new(trans, "en".to_string(), vec!["en".to_string()])
+}
+
+#[test]
+fn test_typed_keys() {
+  let t = translator();
+
+  assert_eq!(keys::ORDER_NOT_FOUND.id(), "order.not_found");
+  assert_eq!(keys::ServerInternalError::ID, "server.internal.error");
+
+  let params = keys::OrderNotFoundParams { id: 5.into() };
+  assert_eq!(t.tr_typed("en", keys::ORDER_NOT_FOUND, params).unwrap(), "Order 5 is not found");
+
+  let params =
+    keys::OrderItemsLeftParams { count: 1.into(), store: serde_json::json!({ "name": "Cairo" }) };
+  assert_eq!(t.tr_typed("en", keys::ORDER_ITEMS_LEFT, params).unwrap(), "1 item left in Cairo");
+
+  // loop variables aren't params, and keywords are raw identifiers
+  let params = keys::OrderShippedParams { items: vec!["a", "b"].into(), r#type: "home".into() };
+  assert_eq!(t.tr_typed("en", keys::ORDER_SHIPPED, params).unwrap(), "a, b, shipped to home");
+
+  assert!(t.tr_typed("en", keys::SERVER_INTERNAL_ERROR, ()).unwrap().starts_with("Sorry"));
+}
+
+#[test]
+fn test_typed_keys_compile_errors() {
+  trybuild::TestCases::new().compile_fail("tests/ui/translation_keys_*.rs");
+}
@@ -0,0 +1,8 @@
+use megacommerce_shared::models::errors::SanitizeAppError;
+
+#[derive(SanitizeAppError)]
+enum Response {
+  Error(megacommerce_proto::AppError),
+}
+
+fn main() {}
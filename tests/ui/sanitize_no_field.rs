@@ -0,0 +1,8 @@
+use megacommerce_shared::models::errors::SanitizeAppError;
+
+#[derive(SanitizeAppError)]
+struct Response {
+  error: Option<megacommerce_proto::AppError>,
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use megacommerce_shared::models::errors::SanitizeAppError;
+
+#[derive(SanitizeAppError)]
+struct Response {
+  #[app_error]
+  error: Option<String>,
+}
+
+fn main() {}
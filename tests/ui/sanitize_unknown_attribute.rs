@@ -0,0 +1,9 @@
+use megacommerce_shared::models::errors::SanitizeAppError;
+
+#[derive(SanitizeAppError)]
+struct Response {
+  #[app_error(variant = Error)]
+  error: Option<megacommerce_proto::AppError>,
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use megacommerce_proto::product_create_response;
+use megacommerce_shared::models::errors::SanitizeAppError;
+
+#[derive(SanitizeAppError)]
+struct Response {
+  #[app_error(oneof(product_create_response::Response::Eror))]
+  response: Option<product_create_response::Response>,
+}
+
+fn main() {}
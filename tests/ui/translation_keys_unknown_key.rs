@@ -0,0 +1,7 @@
+mod keys {
+  megacommerce_shared::translation_keys!("../fixtures/translations/en.yaml");
+}
+
+fn main() {
+  let _ = megacommerce_shared::models::translate::tr_typed("en", keys::ORDER_NOT_FOUD, ());
+}